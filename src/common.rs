@@ -25,4 +25,14 @@ pub mod tests {
        let result = fs::read_to_string(format!("day{day}_sample_input.txt"))?;
        Ok(result)
     }
+
+    /// A repeatable stream of 31-bit numbers, from a linear congruential generator.
+    pub fn pseudo_random(mut seed: u64) -> impl Iterator<Item = u64> {
+        std::iter::repeat_with(move || {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            seed >> 33
+        })
+    }
 }
@@ -1,27 +1,341 @@
 #[cfg(test)]
 mod tests {
     use std::cmp::Reverse;
+    use std::collections::BinaryHeap;
+    use std::error::Error;
+    use std::fmt;
+    use std::io::{self, BufRead};
 
     use crate::common::tests::get_input;
 
+    #[derive(Debug)]
+    enum CalorieError {
+        InvalidLine {
+            line: usize,
+            text: String,
+        },
+        /// The calories of one elf add up to more than a `u64` holds.
+        ElfOverflow {
+            line: usize,
+        },
+        /// The calories of all elves add up to more than a `u64` holds.
+        InventoryOverflow,
+        Io(io::Error),
+    }
+
+    impl fmt::Display for CalorieError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                CalorieError::InvalidLine { line, text } => {
+                    write!(f, "line {line}: {text:?} is not a number of calories")
+                }
+                CalorieError::ElfOverflow { line } => {
+                    write!(f, "line {line}: too many calories for one elf")
+                }
+                CalorieError::InventoryOverflow => write!(f, "too many calories for all elves"),
+                CalorieError::Io(e) => write!(f, "could not read the inventory: {e}"),
+            }
+        }
+    }
+
+    impl Error for CalorieError {}
+
+    impl From<io::Error> for CalorieError {
+        fn from(e: io::Error) -> Self {
+            CalorieError::Io(e)
+        }
+    }
+
+    /// An elf, by its position in the inventory (counting from 0), and the calories it carries.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    struct Elf {
+        index: usize,
+        calories: u64,
+    }
+
+    /// Reads an inventory line by line, calling `on_elf` for every elf as soon as its items have
+    /// been read. Elves are separated by one or more blank lines. Returns the number of elves.
+    fn scan_elves<R: BufRead>(
+        reader: R,
+        mut on_elf: impl FnMut(Elf),
+    ) -> Result<usize, CalorieError> {
+        let mut elves = 0;
+        let mut current: Option<u64> = None;
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() {
+                if let Some(calories) = current.take() {
+                    on_elf(Elf {
+                        index: elves,
+                        calories,
+                    });
+                    elves += 1;
+                }
+                continue;
+            }
+            let calories = line.parse::<u64>().map_err(|_| CalorieError::InvalidLine {
+                line: index + 1,
+                text: line.to_string(),
+            })?;
+            let total = current.unwrap_or(0).checked_add(calories);
+            current = Some(total.ok_or(CalorieError::ElfOverflow { line: index + 1 })?);
+        }
+        if let Some(calories) = current {
+            on_elf(Elf {
+                index: elves,
+                calories,
+            });
+            elves += 1;
+        }
+        Ok(elves)
+    }
+
+    /// The `k` elves carrying the most calories, keeping no more than `k` elves around.
+    /// Of elves carrying the same amount, the ones that come first win.
+    struct TopK {
+        k: usize,
+        // a min-heap, so the elf to drop is always on top
+        heap: BinaryHeap<Reverse<(u64, Reverse<usize>)>>,
+    }
+
+    impl TopK {
+        fn new(k: usize) -> TopK {
+            TopK {
+                k,
+                heap: BinaryHeap::new(),
+            }
+        }
+
+        fn push(&mut self, elf: Elf) {
+            self.heap.push(Reverse((elf.calories, Reverse(elf.index))));
+            if self.heap.len() > self.k {
+                self.heap.pop();
+            }
+        }
+
+        /// The elves, the one carrying the most calories first.
+        fn into_sorted(self) -> Vec<Elf> {
+            self.heap
+                .into_sorted_vec()
+                .into_iter()
+                .map(|Reverse((calories, Reverse(index)))| Elf { index, calories })
+                .collect()
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Statistics {
+        elves: usize,
+        total: u64,
+        min: u64,
+        max: u64,
+        mean: f64,
+        median: f64,
+        bucket_size: u64,
+        /// The number of elves per range of `bucket_size` calories, from the one holding the
+        /// minimum up to the one holding the maximum, empty ranges included.
+        buckets: Vec<(u64, usize)>,
+    }
+
+    impl Statistics {
+        fn new(mut totals: Vec<u64>, bucket_size: u64) -> Result<Option<Statistics>, CalorieError> {
+            assert!(bucket_size > 0, "Buckets can't be empty");
+            if totals.is_empty() {
+                return Ok(None);
+            }
+            totals.sort_unstable();
+            let elves = totals.len();
+            let total = totals
+                .iter()
+                .try_fold(0u64, |sum, &calories| sum.checked_add(calories))
+                .ok_or(CalorieError::InventoryOverflow)?;
+            let (min, max) = (totals[0], totals[elves - 1]);
+            let median = match elves % 2 {
+                1 => totals[elves / 2] as f64,
+                _ => (totals[elves / 2 - 1] as f64 + totals[elves / 2] as f64) / 2.0,
+            };
+            let first = min / bucket_size;
+            let mut buckets = (first..=max / bucket_size)
+                .map(|bucket| (bucket * bucket_size, 0))
+                .collect::<Vec<_>>();
+            for calories in &totals {
+                buckets[(calories / bucket_size - first) as usize].1 += 1;
+            }
+            Ok(Some(Statistics {
+                elves,
+                total,
+                min,
+                max,
+                mean: total as f64 / elves as f64,
+                median,
+                bucket_size,
+                buckets,
+            }))
+        }
+    }
+
+    impl fmt::Display for Statistics {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            writeln!(f, "{} elves carry {} calories", self.elves, self.total)?;
+            writeln!(
+                f,
+                "min {}, max {}, mean {:.1}, median {:.1}",
+                self.min, self.max, self.mean, self.median
+            )?;
+            let width = (self.max / self.bucket_size * self.bucket_size + self.bucket_size - 1)
+                .to_string()
+                .len();
+            for &(start, count) in &self.buckets {
+                writeln!(
+                    f,
+                    "{start:>width$}..{:>width$} {count:>4} {}",
+                    start + self.bucket_size - 1,
+                    "#".repeat(count)
+                )?;
+            }
+            Ok(())
+        }
+    }
+
+    struct CalorieReport {
+        top: Vec<Elf>,
+        statistics: Option<Statistics>,
+    }
+
+    /// Reads an inventory once, keeping the `k` elves carrying the most calories and the totals
+    /// of all elves for the statistics.
+    fn calorie_report<R: BufRead>(
+        reader: R,
+        k: usize,
+        bucket_size: u64,
+    ) -> Result<CalorieReport, CalorieError> {
+        let mut top = TopK::new(k);
+        let mut totals = Vec::new();
+        scan_elves(reader, |elf| {
+            top.push(elf);
+            totals.push(elf.calories);
+        })?;
+        Ok(CalorieReport {
+            top: top.into_sorted(),
+            statistics: Statistics::new(totals, bucket_size)?,
+        })
+    }
+
+    const SAMPLE: &str = "1000\n2000\n3000\n\n4000\n\n5000\n6000\n\n7000\n8000\n9000\n\n10000";
+
+    #[test]
+    fn day1_sample() -> Result<(), Box<dyn std::error::Error>> {
+        let report = calorie_report(SAMPLE.as_bytes(), 3, 5000)?;
+        assert!(
+            report.top
+                == vec![
+                    Elf {
+                        index: 3,
+                        calories: 24000
+                    },
+                    Elf {
+                        index: 2,
+                        calories: 11000
+                    },
+                    Elf {
+                        index: 4,
+                        calories: 10000
+                    },
+                ]
+        );
+        assert!(report.top.iter().map(|elf| elf.calories).sum::<u64>() == 45000);
+
+        let statistics = report.statistics.unwrap();
+        assert!(statistics.elves == 5);
+        assert!(statistics.total == 55000);
+        assert!(statistics.mean == 11000.0);
+        assert!(statistics.median == 10000.0);
+        assert!(statistics.buckets == vec![(0, 1), (5000, 1), (10000, 2), (15000, 0), (20000, 1)]);
+        assert!(statistics.to_string().lines().nth(2) == Some("    0.. 4999    1 #"));
+
+        let top = calorie_report(SAMPLE.as_bytes(), 1, 5000)?.top;
+        assert!(top.len() == 1 && top[0].index == 3);
+        assert!(calorie_report(SAMPLE.as_bytes(), 10, 5000)?.top.len() == 5);
+        assert!(
+            calorie_report(SAMPLE.as_bytes(), usize::MAX, 5000)?
+                .top
+                .len()
+                == 5
+        );
+        assert!(calorie_report(SAMPLE.as_bytes(), 0, 5000)?.top.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn day1_inventories() -> Result<(), Box<dyn std::error::Error>> {
+        // ties go to the first elf, blank lines in a row don't make empty elves
+        let report = calorie_report("5\n\n\n\n3\n2\n\n4\r\n\r\n5\n".as_bytes(), 2, 2)?;
+        assert!(
+            report.top
+                == vec![
+                    Elf {
+                        index: 0,
+                        calories: 5
+                    },
+                    Elf {
+                        index: 1,
+                        calories: 5
+                    }
+                ]
+        );
+        let statistics = report.statistics.unwrap();
+        assert!(statistics.median == 5.0);
+        assert!(statistics.buckets == vec![(4, 4)]);
+
+        let report = calorie_report("".as_bytes(), 3, 1000)?;
+        assert!(report.top.is_empty() && report.statistics.is_none());
+
+        assert!(matches!(
+            calorie_report("100\n\n2OO\n".as_bytes(), 3, 1000),
+            Err(CalorieError::InvalidLine { line: 3, text }) if text == "2OO"
+        ));
+        assert!(matches!(
+            calorie_report("-1".as_bytes(), 3, 1000),
+            Err(CalorieError::InvalidLine { line: 1, .. })
+        ));
+        assert!(matches!(
+            calorie_report(&[b'1', 0xff][..], 3, 1000),
+            Err(CalorieError::Io(_))
+        ));
+        assert!(matches!(
+            calorie_report("1\n\n18446744073709551615\n1".as_bytes(), 3, 1000),
+            Err(CalorieError::ElfOverflow { line: 4 })
+        ));
+        assert!(matches!(
+            calorie_report(
+                "9223372036854775808\n\n9223372036854775808".as_bytes(),
+                3,
+                1 << 62
+            ),
+            Err(CalorieError::InventoryOverflow)
+        ));
+
+        Ok(())
+    }
+
     #[test]
     fn day1() -> Result<(), Box<dyn std::error::Error>> {
         let input = get_input(1)?;
-        let mut calories: Vec<u32> = Vec::new();
-        for section in input.split("\n\n") {
-            let mut current: u32 = 0;
-            for line in section.lines() {
-                current = current + line.parse::<u32>()?;
-            }
-            calories.push(current);
-        }
-        calories.sort_by_key(|w| Reverse(*w));
+        let report = calorie_report(input.as_bytes(), 3, 5000)?;
 
-        println!("Max calories: {}", calories[0]);
+        println!("Max calories: {}", report.top[0].calories);
         println!(
             "Sum of calories of elves carrying most calories: {}",
-            calories.into_iter().take(3).sum::<u32>()
+            report.top.iter().map(|elf| elf.calories).sum::<u64>()
         );
+        for elf in &report.top {
+            println!("elf {}: {} calories", elf.index, elf.calories);
+        }
+        if let Some(statistics) = report.statistics {
+            print!("{statistics}");
+        }
         Ok(())
     }
 }
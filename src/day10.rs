@@ -1,15 +1,32 @@
 #[cfg(test)]
 mod tests {
-    use std::error::Error;
+    use std::{collections::HashMap, error::Error, fmt, fmt::Write, str::FromStr};
 
-    use crate::common::tests::get_input;
+    use itertools::Itertools;
 
+    use crate::common::tests::{get_input, pseudo_random};
+
+    const SCREEN_WIDTH: usize = 40;
+    const SCREEN_HEIGHT: usize = 6;
+
+    type Bitmap = [[bool; SCREEN_WIDTH]; SCREEN_HEIGHT];
+
+    /// What the CPU needs to know about an instruction: how long it takes and what it does once
+    /// it completes. New opcodes can be added by implementing this for another instruction set,
+    /// without touching the CPU or its observers.
+    trait Operation: fmt::Display {
+        fn cycles(&self) -> usize;
+
+        fn execute(&self, register: &mut i32);
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
     enum Instruction {
         Noop,
         AddX(i32),
     }
 
-    impl Instruction {
+    impl Operation for Instruction {
         fn cycles(&self) -> usize {
             match *self {
                 Instruction::Noop => 1,
@@ -25,54 +42,554 @@ mod tests {
         }
     }
 
-    #[test]
-    fn day10() -> Result<(), Box<dyn Error>> {
-        let input = get_input(10)?;
+    #[derive(Debug)]
+    struct ParseInstructionError(String);
+
+    impl fmt::Display for ParseInstructionError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "invalid instruction: {}", self.0)
+        }
+    }
+
+    impl Error for ParseInstructionError {}
+
+    /// Splits a line in its mnemonic and its (optional) numeric operand.
+    fn split_instruction(s: &str) -> Result<(&str, Option<i32>), ParseInstructionError> {
+        let mut parts = s.split_whitespace();
+        let mnemonic = parts
+            .next()
+            .ok_or_else(|| ParseInstructionError("empty line".to_string()))?;
+        let operand = parts
+            .next()
+            .map(|operand| {
+                operand
+                    .parse()
+                    .map_err(|_| ParseInstructionError(format!("bad operand in '{s}'")))
+            })
+            .transpose()?;
+        if parts.next().is_some() {
+            Err(ParseInstructionError(format!("too many operands in '{s}'")))?
+        }
+        Ok((mnemonic, operand))
+    }
+
+    impl FromStr for Instruction {
+        type Err = ParseInstructionError;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match split_instruction(s)? {
+                ("noop", None) => Ok(Instruction::Noop),
+                ("addx", Some(inc)) => Ok(Instruction::AddX(inc)),
+                _ => Err(ParseInstructionError(format!("unrecognized '{s}'"))),
+            }
+        }
+    }
+
+    impl fmt::Display for Instruction {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                Instruction::Noop => write!(f, "noop"),
+                Instruction::AddX(inc) => write!(f, "addx {inc}"),
+            }
+        }
+    }
+
+    fn parse_program<I: FromStr<Err = ParseInstructionError>>(
+        input: &str,
+    ) -> Result<Vec<I>, ParseInstructionError> {
+        input
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| {
+                line.parse().map_err(|e: ParseInstructionError| {
+                    ParseInstructionError(format!("line {}: {}", index + 1, e.0))
+                })
+            })
+            .collect()
+    }
+
+    /// Gets told the value of the X register during every cycle.
+    trait CycleObserver {
+        fn during(&mut self, cycle: usize, x: i32);
+    }
 
-        let mut x_register = 1i32;
-        let mut completed_cycles = 0usize;
-
-        let mut signal_strength_sum = 0i32;
-        for line in input.lines() {
-            let instruction = if line.starts_with("noop") {
-                Instruction::Noop
-            } else if line.starts_with("addx ") {
-                let to_add: i32 = (&line[5..]).parse()?;
-                Instruction::AddX(to_add)
-            } else {
-                panic!("Unrecognized operand");
+    /// Executes a program one cycle at a time.
+    struct Cpu<'a, I> {
+        program: &'a [I],
+        x: i32,
+        /// The number of completed cycles.
+        cycle: usize,
+        /// The instruction being executed, and the cycles already spent on it.
+        pc: usize,
+        progress: usize,
+    }
+
+    impl<'a, I: Operation> Cpu<'a, I> {
+        fn new(program: &'a [I]) -> Cpu<'a, I> {
+            Cpu {
+                program,
+                x: 1,
+                cycle: 0,
+                pc: 0,
+                progress: 0,
+            }
+        }
+
+        fn is_halted(&self) -> bool {
+            self.pc >= self.program.len()
+        }
+
+        /// Runs a single cycle, returning its number and the value of X during it,
+        /// or `None` when the program has ended.
+        fn step(&mut self) -> Option<(usize, i32)> {
+            let instruction = self.program.get(self.pc)?;
+            self.cycle += 1;
+            let during = (self.cycle, self.x);
+
+            self.progress += 1;
+            if self.progress >= instruction.cycles() {
+                instruction.execute(&mut self.x);
+                self.pc += 1;
+                self.progress = 0;
+            }
+            Some(during)
+        }
+
+        fn run(&mut self, observers: &mut [&mut dyn CycleObserver]) {
+            while let Some((cycle, x)) = self.step() {
+                for observer in observers.iter_mut() {
+                    observer.during(cycle, x);
+                }
+            }
+        }
+    }
+
+    /// Sums the signal strength during the 20th cycle and every 40 cycles after that.
+    #[derive(Default)]
+    struct SignalStrength {
+        sum: i32,
+    }
+
+    impl CycleObserver for SignalStrength {
+        fn during(&mut self, cycle: usize, x: i32) {
+            if cycle % SCREEN_WIDTH == 20 {
+                self.sum += cycle as i32 * x;
+            }
+        }
+    }
+
+    /// The screen, drawing one pixel per cycle, lit if the sprite at X covers it.
+    struct Crt {
+        pixels: Bitmap,
+    }
+
+    impl Crt {
+        fn new() -> Crt {
+            Crt {
+                pixels: [[false; SCREEN_WIDTH]; SCREEN_HEIGHT],
+            }
+        }
+
+        fn render(&self) -> String {
+            render_bitmap(&self.pixels)
+        }
+    }
+
+    impl CycleObserver for Crt {
+        fn during(&mut self, cycle: usize, x: i32) {
+            let position = (cycle - 1) % (SCREEN_WIDTH * SCREEN_HEIGHT);
+            let (row, column) = (position / SCREEN_WIDTH, position % SCREEN_WIDTH);
+            self.pixels[row][column] = (column as i32 - x).abs() <= 1;
+        }
+    }
+
+    /// A listing of the program, with the cycles every instruction runs in and its effect on X.
+    fn disassemble<I: Operation>(program: &[I]) -> String {
+        let mut listing = String::new();
+        let mut x = 1;
+        let mut cycle = 1;
+        let total_cycles: usize = program.iter().map(|instruction| instruction.cycles()).sum();
+        let address_width = program.len().max(1).to_string().len();
+        let cycles_width = format!("{total_cycles}-{total_cycles}").len();
+        for (address, instruction) in program.iter().enumerate() {
+            let cycles = instruction.cycles();
+            let before = x;
+            instruction.execute(&mut x);
+            let cycle_range = match cycles {
+                1 => format!("{cycle}"),
+                _ => format!("{cycle}-{}", cycle + cycles - 1),
             };
+            let effect = match before == x {
+                true => format!("X = {x}"),
+                false => format!("X = {before} -> {x}"),
+            };
+            writeln!(
+                listing,
+                "{address:>address_width$}  {cycle_range:>cycles_width$}  {:<12} ; {effect}",
+                instruction.to_string()
+            )
+            .unwrap();
+            cycle += cycles;
+        }
+        listing
+    }
+
+    fn render_bitmap(bitmap: &Bitmap) -> String {
+        bitmap
+            .iter()
+            .map(|row| row.iter().map(|&lit| if lit { '█' } else { '.' }).join(""))
+            .join("\n")
+    }
+
+    #[derive(Debug, PartialEq, Eq)]
+    enum DrawError {
+        InvalidBitmap(String),
+        UnknownGlyph(char),
+        TextTooLong(usize),
+        /// No program can get the pixel right together with all the pixels drawn before it.
+        Undrawable {
+            row: usize,
+            column: usize,
+        },
+    }
+
+    impl fmt::Display for DrawError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                DrawError::InvalidBitmap(reason) => write!(f, "invalid bitmap: {reason}"),
+                DrawError::UnknownGlyph(c) => write!(f, "no glyph for '{c}'"),
+                DrawError::TextTooLong(length) => write!(
+                    f,
+                    "{length} characters don't fit on the screen, at most {} do",
+                    SCREEN_WIDTH / GLYPH_ADVANCE
+                ),
+                DrawError::Undrawable { row, column } => {
+                    write!(f, "the pixel at row {row}, column {column} can't be drawn")
+                }
+            }
+        }
+    }
+
+    impl Error for DrawError {}
 
-            let cycles_for_current_op = instruction.cycles();
+    /// Reads a bitmap in the format the CRT is rendered in, with `#` or `█` for lit pixels.
+    fn parse_bitmap(text: &str) -> Result<Bitmap, DrawError> {
+        let mut bitmap = [[false; SCREEN_WIDTH]; SCREEN_HEIGHT];
+        let rows = text.lines().collect_vec();
+        if rows.len() != SCREEN_HEIGHT {
+            Err(DrawError::InvalidBitmap(format!("{} rows", rows.len())))?
+        }
+        for (row, line) in rows.into_iter().enumerate() {
+            let pixels = line.chars().collect_vec();
+            if pixels.len() != SCREEN_WIDTH {
+                Err(DrawError::InvalidBitmap(format!(
+                    "{} pixels on row {row}",
+                    pixels.len()
+                )))?
+            }
+            for (column, pixel) in pixels.into_iter().enumerate() {
+                bitmap[row][column] = match pixel {
+                    '#' | '█' => true,
+                    '.' => false,
+                    c => Err(DrawError::InvalidBitmap(format!("unexpected '{c}'")))?,
+                };
+            }
+        }
+        Ok(bitmap)
+    }
 
-            //will the 20th, 60th, 100th, ... cycle be between the start of and of the current operand execution?
-            let adjusted_current_cycle = completed_cycles % 40;
-            if adjusted_current_cycle < 20 && adjusted_current_cycle + cycles_for_current_op >= 20 {
-                //hit! the current X value is the one we need
-                let relevant_cycle = completed_cycles - adjusted_current_cycle + 20;
-                signal_strength_sum += x_register * (relevant_cycle as i32);
+    const GLYPH_WIDTH: usize = 4;
+    const GLYPH_ADVANCE: usize = GLYPH_WIDTH + 1;
+
+    /// The letters as the puzzles draw them, four pixels wide with a blank column in between.
+    const GLYPHS: [(char, [&str; SCREEN_HEIGHT]); 19] = [
+        ('A', [".##.", "#..#", "#..#", "####", "#..#", "#..#"]),
+        ('B', ["###.", "#..#", "###.", "#..#", "#..#", "###."]),
+        ('C', [".##.", "#..#", "#...", "#...", "#..#", ".##."]),
+        ('E', ["####", "#...", "###.", "#...", "#...", "####"]),
+        ('F', ["####", "#...", "###.", "#...", "#...", "#..."]),
+        ('G', [".##.", "#..#", "#...", "#.##", "#..#", ".###"]),
+        ('H', ["#..#", "#..#", "####", "#..#", "#..#", "#..#"]),
+        ('I', [".###", "..#.", "..#.", "..#.", "..#.", ".###"]),
+        ('J', ["..##", "...#", "...#", "...#", "#..#", ".##."]),
+        ('K', ["#..#", "#.#.", "##..", "#.#.", "#.#.", "#..#"]),
+        ('L', ["#...", "#...", "#...", "#...", "#...", "####"]),
+        ('O', [".##.", "#..#", "#..#", "#..#", "#..#", ".##."]),
+        ('P', ["###.", "#..#", "#..#", "###.", "#...", "#..."]),
+        ('R', ["###.", "#..#", "#..#", "###.", "#.#.", "#..#"]),
+        ('S', [".###", "#...", "#...", ".##.", "...#", "###."]),
+        ('U', ["#..#", "#..#", "#..#", "#..#", "#..#", ".##."]),
+        ('Z', ["####", "...#", "..#.", ".#..", "#...", "####"]),
+        ('0', [".##.", "#..#", "#.##", "##.#", "#..#", ".##."]),
+        (' ', ["....", "....", "....", "....", "....", "...."]),
+    ];
+
+    /// Renders text in the puzzle font, starting at the left edge of the screen.
+    fn render_text(text: &str) -> Result<Bitmap, DrawError> {
+        let length = text.chars().count();
+        if length > SCREEN_WIDTH / GLYPH_ADVANCE {
+            Err(DrawError::TextTooLong(length))?
+        }
+        let mut bitmap = [[false; SCREEN_WIDTH]; SCREEN_HEIGHT];
+        for (index, c) in text.chars().enumerate() {
+            let (_, glyph) = GLYPHS
+                .iter()
+                .find(|(g, _)| *g == c.to_ascii_uppercase())
+                .ok_or(DrawError::UnknownGlyph(c))?;
+            for (row, line) in glyph.iter().enumerate() {
+                for (offset, pixel) in line.chars().enumerate() {
+                    bitmap[row][index * GLYPH_ADVANCE + offset] = pixel == '#';
+                }
             }
+        }
+        Ok(bitmap)
+    }
+
+    /// The sprite positions worth considering: every position that lights a pixel, and one on
+    /// either side that lights none.
+    const SPRITE_POSITIONS: std::ops::RangeInclusive<i32> = -2..=SCREEN_WIDTH as i32 + 1;
 
-            //we know the value of X for the coming #cycles_for_current_op cycles
-            for i in 0..cycles_for_current_op {
-                let pixel_being_drawn = (completed_cycles + i) % 40;
-                let pixel_being_drawn = pixel_being_drawn as i32;
+    /// Finds a `noop`/`addx` program that makes the CRT show the bitmap.
+    ///
+    /// X can only change when an `addx` completes, so it stays the same for at least two cycles
+    /// before every change. A search over the cycles, with X and whether it could change as the
+    /// state, finds a value for X during every cycle; runs of the same value then become `noop`s
+    /// followed by an `addx` to the next value.
+    fn synthesize(target: &Bitmap) -> Result<Vec<Instruction>, DrawError> {
+        let pixel_count = SCREEN_WIDTH * SCREEN_HEIGHT;
+        let lights = |cycle: usize, x: i32| {
+            let column = (cycle % SCREEN_WIDTH) as i32;
+            (column - x).abs() <= 1
+        };
+        let target_at = |cycle: usize| target[cycle / SCREEN_WIDTH][cycle % SCREEN_WIDTH];
 
-                if pixel_being_drawn >= x_register - 1 && pixel_being_drawn <= x_register + 1 {
-                    print!("█");
-                } else {
-                    print!(".");
+        // for every cycle, how every reachable (X, can change) state was reached
+        type State = (i32, bool);
+        let mut reached: Vec<HashMap<State, Option<State>>> = Vec::with_capacity(pixel_count);
+        if lights(0, 1) != target_at(0) {
+            Err(DrawError::Undrawable { row: 0, column: 0 })?
+        }
+        reached.push(HashMap::from([((1, false), None)]));
+        for cycle in 1..pixel_count {
+            let mut next = HashMap::new();
+            for &(x, can_change) in reached[cycle - 1].keys().sorted() {
+                let successors = std::iter::once((x, true))
+                    .chain(SPRITE_POSITIONS.filter(|_| can_change).map(|x| (x, false)));
+                for successor in successors {
+                    if lights(cycle, successor.0) == target_at(cycle) {
+                        next.entry(successor).or_insert(Some((x, can_change)));
+                    }
                 }
+            }
+            if next.is_empty() {
+                Err(DrawError::Undrawable {
+                    row: cycle / SCREEN_WIDTH,
+                    column: cycle % SCREEN_WIDTH,
+                })?
+            }
+            reached.push(next);
+        }
+
+        let mut state = *reached[pixel_count - 1].keys().sorted().next().unwrap();
+        let mut xs = vec![state.0];
+        for cycle in (1..pixel_count).rev() {
+            state = reached[cycle][&state].unwrap();
+            xs.push(state.0);
+        }
+        xs.reverse();
 
-                if pixel_being_drawn == 39 {
-                    println!();
+        let mut program = Vec::new();
+        let runs = xs.iter().dedup_with_count().collect_vec();
+        for (index, &(length, &x)) in runs.iter().enumerate() {
+            match runs.get(index + 1) {
+                Some(&(_, &next)) => {
+                    program.extend(std::iter::repeat_n(Instruction::Noop, length - 2));
+                    program.push(Instruction::AddX(next - x));
                 }
+                None => program.extend(std::iter::repeat_n(Instruction::Noop, length)),
             }
+        }
 
-            instruction.execute(&mut x_register);
-            completed_cycles += cycles_for_current_op;
+        // feed it back through the emulator, to be sure
+        let mut crt = Crt::new();
+        Cpu::new(&program).run(&mut [&mut crt]);
+        assert!(
+            &crt.pixels == target,
+            "the synthesized program draws\n{}",
+            crt.render()
+        );
+        Ok(program)
+    }
+
+    fn run_program(input: &str) -> Result<(i32, String), Box<dyn Error>> {
+        let program: Vec<Instruction> = parse_program(input)?;
+        let mut signal_strength = SignalStrength::default();
+        let mut crt = Crt::new();
+        Cpu::new(&program).run(&mut [&mut signal_strength, &mut crt]);
+        Ok((signal_strength.sum, crt.render()))
+    }
+
+    #[test]
+    fn day10_cpu() -> Result<(), Box<dyn Error>> {
+        let program: Vec<Instruction> = parse_program("noop\naddx 3\naddx -5")?;
+        let mut cpu = Cpu::new(&program);
+        let mut during = Vec::new();
+        while let Some(state) = cpu.step() {
+            during.push(state);
         }
+        assert!(during == vec![(1, 1), (2, 1), (3, 1), (4, 4), (5, 4)]);
+        assert!(cpu.is_halted());
+        assert!(cpu.x == -1);
+
+        assert!(parse_program::<Instruction>("noop\nmulx 3").is_err());
+        assert!(parse_program::<Instruction>("addx").is_err());
+        assert!(parse_program::<Instruction>("noop 1").is_err());
+
+        // with X at 1 all the time, the three leftmost pixels of every row are lit
+        let (signal_strength, screen) = run_program(&"noop\n".repeat(240))?;
+        assert!(signal_strength == 20 + 60 + 100 + 140 + 180 + 220);
+        assert!(screen
+            .lines()
+            .all(|row| row == format!("███{}", ".".repeat(37))));
+
+        // the sprite keeps up with the beam during the first row, and is off screen after that
+        let (signal_strength, screen) = run_program(&"addx 2\n".repeat(120))?;
+        assert!(
+            signal_strength
+                == [20, 60, 100, 140, 180, 220]
+                    .iter()
+                    .map(|c| c * (c - 1))
+                    .sum::<i32>()
+        );
+        assert!(screen.lines().next() == Some("█".repeat(40).as_str()));
+        assert!(screen.lines().skip(1).all(|row| row == ".".repeat(40)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn day10_disassembler() -> Result<(), Box<dyn Error>> {
+        let program: Vec<Instruction> = parse_program("noop\naddx 3\naddx -5")?;
+        let listing = disassemble(&program);
+        assert!(
+            listing
+                == "0    1  noop         ; X = 1\n\
+                    1  2-3  addx 3       ; X = 1 -> 4\n\
+                    2  4-5  addx -5      ; X = 4 -> -1\n"
+        );
+
+        // the listing shows the instructions in their source form, so it can be parsed back
+        let source = program.iter().join("\n");
+        assert!(parse_program::<Instruction>(&source)? == program);
+
+        Ok(())
+    }
+
+    /// An extended instruction set, to show new opcodes only need an `Operation` implementation.
+    #[derive(Debug, PartialEq)]
+    enum Extended {
+        Base(Instruction),
+        MulX(i32),
+    }
+
+    impl Operation for Extended {
+        fn cycles(&self) -> usize {
+            match self {
+                Extended::Base(instruction) => instruction.cycles(),
+                Extended::MulX(_) => 3,
+            }
+        }
+
+        fn execute(&self, register: &mut i32) {
+            match self {
+                Extended::Base(instruction) => instruction.execute(register),
+                Extended::MulX(factor) => *register *= factor,
+            }
+        }
+    }
+
+    impl FromStr for Extended {
+        type Err = ParseInstructionError;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match split_instruction(s)? {
+                ("mulx", Some(factor)) => Ok(Extended::MulX(factor)),
+                _ => s.parse().map(Extended::Base),
+            }
+        }
+    }
+
+    impl fmt::Display for Extended {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                Extended::Base(instruction) => instruction.fmt(f),
+                Extended::MulX(factor) => write!(f, "mulx {factor}"),
+            }
+        }
+    }
+
+    #[test]
+    fn day10_new_opcodes() -> Result<(), Box<dyn Error>> {
+        let program: Vec<Extended> = parse_program("addx 2\nmulx 5\nnoop")?;
+        assert!(program[1] == Extended::MulX(5));
+
+        let mut cpu = Cpu::new(&program);
+        let during = std::iter::from_fn(|| cpu.step()).collect_vec();
+        assert!(during == vec![(1, 1), (2, 1), (3, 3), (4, 3), (5, 3), (6, 15)]);
+        assert!(disassemble(&program).lines().nth(1) == Some("1  3-5  mulx 5       ; X = 3 -> 15"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn day10_synthesizer() -> Result<(), Box<dyn Error>> {
+        for text in ["BCEFGHIJ", "RKLOPSUZ", "EHZFZHCZ", "FO0 A J", "Z"] {
+            let bitmap = render_text(text)?;
+            let program = synthesize(&bitmap).map_err(|e| format!("{text}: {e}"))?;
+            let (_, screen) = run_program(&program.iter().join("\n"))?;
+            assert!(parse_bitmap(&screen)? == bitmap, "{text}");
+        }
+
+        // a bitmap drawn by some (short enough) program can always be drawn again
+        let program = pseudo_random(42)
+            .take(100)
+            .map(|random| match random >> 28 {
+                0 | 1 => "noop".to_string(),
+                r => format!("addx {}", random as i32 % 9 - 4 + r as i32 - 4),
+            })
+            .join("\n");
+        let (_, screen) = run_program(&program)?;
+        let bitmap = parse_bitmap(&screen)?;
+        synthesize(&bitmap)?;
+
+        // the sprite starts at 1, and can't move away after a single cycle
+        let mut bitmap = [[false; SCREEN_WIDTH]; SCREEN_HEIGHT];
+        bitmap[0][0] = true;
+        assert!(synthesize(&bitmap) == Err(DrawError::Undrawable { row: 0, column: 1 }));
+        bitmap[0][0] = false;
+        assert!(synthesize(&bitmap) == Err(DrawError::Undrawable { row: 0, column: 0 }));
+
+        // only letters that start with two lit pixels fit the sprite's starting position
+        assert!(
+            synthesize(&render_text("KL")?) == Err(DrawError::Undrawable { row: 0, column: 1 })
+        );
+        assert!(render_text("ABCDEFGHI") == Err(DrawError::TextTooLong(9)));
+        assert!(render_text("W") == Err(DrawError::UnknownGlyph('W')));
+        assert!(matches!(
+            parse_bitmap("#."),
+            Err(DrawError::InvalidBitmap(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn day10() -> Result<(), Box<dyn Error>> {
+        let input = get_input(10)?;
+        let (signal_strength_sum, screen) = run_program(&input)?;
 
+        println!("{screen}");
         println!("Sum of signal strengths: {signal_strength_sum}");
         Ok(())
     }
@@ -1,43 +1,320 @@
 #[cfg(test)]
 mod tests {
-    use std::{cell::RefCell, error::Error};
+    use std::{
+        collections::HashMap, error::Error, fmt, hash::Hash, iter::Peekable, rc::Rc, str::FromStr,
+    };
 
     use itertools::Itertools;
+    use num::{Integer, Zero};
+    use num_bigint::BigUint;
 
     use crate::common::tests::{get_input, get_sample_input};
 
-    const STARTING_ITEMS_PREFIX: &str = "  Starting items: ";
-    const OPERATIONS_PREFIX: &str = "  Operation: new = old ";
-    const TEST_PREFIX: &str = "  Test: divisible by ";
-    const POSITIVE_ACTION_PREFIX: &str = "    If true: throw to monkey ";
-    const NEGATIVE_ACTION_PREFIX: &str = "    If false: throw to monkey ";
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    enum BinaryOp {
+        Add,
+        Sub,
+        Mul,
+        Div,
+    }
 
-    #[derive(Clone, Copy, Debug)]
-    enum Op {
-        Add(usize),
-        Mul(usize),
-        Square,
+    /// The right hand side of a `new = ...` operation.
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    enum Expr {
+        Old,
+        Number(usize),
+        Binary(BinaryOp, Box<Expr>, Box<Expr>),
     }
 
-    impl Op {
-        fn execute(&self, old: usize) -> usize {
+    #[derive(Debug, PartialEq, Eq)]
+    struct ExpressionError(String);
+
+    impl fmt::Display for ExpressionError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{}", self.0)
+        }
+    }
+
+    impl Error for ExpressionError {}
+
+    impl Expr {
+        /// Evaluates the expression, or `None` if the worry arithmetic can't represent the result.
+        fn evaluate<W: Worry>(&self, old: &W) -> Option<W> {
             match self {
-                &Op::Add(a) => old + a,
-                &Op::Mul(m) => old * m,
-                &Op::Square => old * old,
+                Expr::Old => Some(old.clone()),
+                Expr::Number(n) => Some(old.constant(*n)),
+                Expr::Binary(op, left, right) => {
+                    left.evaluate(old)?.apply(*op, &right.evaluate(old)?)
+                }
+            }
+        }
+
+        /// expression := term (('+' | '-') term)*
+        fn parse_expression<'a, I: Iterator<Item = &'a str>>(
+            tokens: &mut Peekable<I>,
+        ) -> Result<Expr, ExpressionError> {
+            let mut expr = Expr::parse_term(tokens)?;
+            while let Some(op) = tokens.next_if(|t| *t == "+" || *t == "-") {
+                let op = if op == "+" {
+                    BinaryOp::Add
+                } else {
+                    BinaryOp::Sub
+                };
+                expr = Expr::Binary(op, Box::new(expr), Box::new(Expr::parse_term(tokens)?));
+            }
+            Ok(expr)
+        }
+
+        /// term := operand (('*' | '/') operand)*
+        fn parse_term<'a, I: Iterator<Item = &'a str>>(
+            tokens: &mut Peekable<I>,
+        ) -> Result<Expr, ExpressionError> {
+            let mut expr = Expr::parse_operand(tokens)?;
+            while let Some(op) = tokens.next_if(|t| *t == "*" || *t == "/") {
+                let op = if op == "*" {
+                    BinaryOp::Mul
+                } else {
+                    BinaryOp::Div
+                };
+                expr = Expr::Binary(op, Box::new(expr), Box::new(Expr::parse_operand(tokens)?));
+            }
+            Ok(expr)
+        }
+
+        /// operand := 'old' | number | '(' expression ')'
+        fn parse_operand<'a, I: Iterator<Item = &'a str>>(
+            tokens: &mut Peekable<I>,
+        ) -> Result<Expr, ExpressionError> {
+            match tokens.next() {
+                Some("old") => Ok(Expr::Old),
+                Some("(") => {
+                    let expr = Expr::parse_expression(tokens)?;
+                    match tokens.next() {
+                        Some(")") => Ok(expr),
+                        _ => Err(ExpressionError("missing ')'".to_string())),
+                    }
+                }
+                Some(token) => token
+                    .parse()
+                    .map(Expr::Number)
+                    .map_err(|_| ExpressionError(format!("unexpected '{token}'"))),
+                None => Err(ExpressionError("unexpected end of expression".to_string())),
+            }
+        }
+    }
+
+    impl FromStr for Expr {
+        type Err = ExpressionError;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            // split into numbers, words and single character operators
+            let mut tokens = Vec::new();
+            let mut rest = s.trim();
+            while !rest.is_empty() {
+                let length = match rest.find(|c: char| !c.is_ascii_alphanumeric()) {
+                    Some(0) => rest.chars().next().unwrap().len_utf8(),
+                    Some(end) => end,
+                    None => rest.len(),
+                };
+                tokens.push(&rest[..length]);
+                rest = rest[length..].trim_start();
+            }
+
+            let mut tokens = tokens.into_iter().peekable();
+            let expr = Expr::parse_expression(&mut tokens)?;
+            match tokens.next() {
+                None => Ok(expr),
+                Some(token) => Err(ExpressionError(format!("unexpected '{token}'"))),
+            }
+        }
+    }
+
+    /// The arithmetic used for worry levels.
+    trait Worry: Clone + fmt::Debug {
+        /// A constant in the same arithmetic as `self`.
+        fn constant(&self, n: usize) -> Self;
+        fn apply(&self, op: BinaryOp, other: &Self) -> Option<Self>;
+        /// Divides by the relief factor, rounding down.
+        fn relieve(&self, factor: usize) -> Option<Self>;
+        fn is_divisible_by(&self, divisor: usize) -> bool;
+    }
+
+    /// Plain machine integers: valid as long as nothing overflows or goes below zero.
+    impl Worry for usize {
+        fn constant(&self, n: usize) -> Self {
+            n
+        }
+
+        fn apply(&self, op: BinaryOp, other: &Self) -> Option<Self> {
+            match op {
+                BinaryOp::Add => self.checked_add(*other),
+                BinaryOp::Sub => self.checked_sub(*other),
+                BinaryOp::Mul => self.checked_mul(*other),
+                BinaryOp::Div => self.checked_div(*other),
+            }
+        }
+
+        fn relieve(&self, factor: usize) -> Option<Self> {
+            self.checked_div(factor)
+        }
+
+        fn is_divisible_by(&self, divisor: usize) -> bool {
+            Integer::is_multiple_of(self, &divisor)
+        }
+    }
+
+    /// Exact arithmetic: always valid, but the numbers keep growing without relief,
+    /// so it's only feasible for a limited number of rounds.
+    impl Worry for BigUint {
+        fn constant(&self, n: usize) -> Self {
+            BigUint::from(n)
+        }
+
+        fn apply(&self, op: BinaryOp, other: &Self) -> Option<Self> {
+            match op {
+                BinaryOp::Add => Some(self + other),
+                BinaryOp::Sub => num::CheckedSub::checked_sub(self, other),
+                BinaryOp::Mul => Some(self * other),
+                BinaryOp::Div => num::CheckedDiv::checked_div(self, other),
+            }
+        }
+
+        fn relieve(&self, factor: usize) -> Option<Self> {
+            num::CheckedDiv::checked_div(self, &BigUint::from(factor))
+        }
+
+        fn is_divisible_by(&self, divisor: usize) -> bool {
+            (self % divisor).is_zero()
+        }
+    }
+
+    /// Worry levels modulo the least common multiple of all divisors.
+    ///
+    /// Every divisibility test only depends on the value modulo its divisor, which divides the lcm,
+    /// so this is valid for operations built from `+`, `*` and `-` (as long as the exact value
+    /// wouldn't go below zero). It is not valid for `/` or for relief, which don't commute with
+    /// taking a remainder, so those are refused.
+    #[derive(Clone, Debug, PartialEq, Eq, Hash)]
+    struct LcmModular {
+        value: u64,
+        modulus: u64,
+    }
+
+    impl LcmModular {
+        fn new(value: usize, divisors: &[usize]) -> LcmModular {
+            let modulus = divisors.iter().fold(1u64, |lcm, &d| lcm.lcm(&(d as u64)));
+            LcmModular {
+                value: value as u64 % modulus,
+                modulus,
             }
         }
     }
 
+    impl Worry for LcmModular {
+        fn constant(&self, n: usize) -> Self {
+            LcmModular {
+                value: n as u64 % self.modulus,
+                modulus: self.modulus,
+            }
+        }
+
+        fn apply(&self, op: BinaryOp, other: &Self) -> Option<Self> {
+            let (a, b, m) = (
+                self.value as u128,
+                other.value as u128,
+                self.modulus as u128,
+            );
+            let value = match op {
+                BinaryOp::Add => (a + b) % m,
+                BinaryOp::Sub => (a + m - b) % m,
+                BinaryOp::Mul => (a * b) % m,
+                BinaryOp::Div => return None,
+            };
+            Some(LcmModular {
+                value: value as u64,
+                modulus: self.modulus,
+            })
+        }
+
+        fn relieve(&self, factor: usize) -> Option<Self> {
+            (factor == 1).then(|| self.clone())
+        }
+
+        fn is_divisible_by(&self, divisor: usize) -> bool {
+            assert!(Integer::is_multiple_of(&self.modulus, &(divisor as u64)));
+            Integer::is_multiple_of(&self.value, &(divisor as u64))
+        }
+    }
+
+    /// The remainder of the worry level for every divisor separately.
+    ///
+    /// Valid under the same conditions as `LcmModular`, but never needs the lcm itself,
+    /// so it keeps working when the lcm would overflow.
+    #[derive(Clone, Debug, PartialEq, Eq, Hash)]
+    struct Residues {
+        divisors: Rc<[usize]>,
+        residues: Vec<usize>,
+    }
+
+    impl Residues {
+        fn new(value: usize, divisors: Rc<[usize]>) -> Residues {
+            let residues = divisors.iter().map(|d| value % d).collect();
+            Residues { divisors, residues }
+        }
+
+        fn combine(&self, other: &Self, f: impl Fn(u128, u128, u128) -> u128) -> Self {
+            let residues = self
+                .divisors
+                .iter()
+                .zip(self.residues.iter().zip(other.residues.iter()))
+                .map(|(&d, (&a, &b))| f(a as u128, b as u128, d as u128) as usize)
+                .collect();
+            Residues {
+                divisors: self.divisors.clone(),
+                residues,
+            }
+        }
+    }
+
+    impl Worry for Residues {
+        fn constant(&self, n: usize) -> Self {
+            Residues::new(n, self.divisors.clone())
+        }
+
+        fn apply(&self, op: BinaryOp, other: &Self) -> Option<Self> {
+            match op {
+                BinaryOp::Add => Some(self.combine(other, |a, b, d| (a + b) % d)),
+                BinaryOp::Sub => Some(self.combine(other, |a, b, d| (a + d - b) % d)),
+                BinaryOp::Mul => Some(self.combine(other, |a, b, d| (a * b) % d)),
+                BinaryOp::Div => None,
+            }
+        }
+
+        fn relieve(&self, factor: usize) -> Option<Self> {
+            (factor == 1).then(|| self.clone())
+        }
+
+        fn is_divisible_by(&self, divisor: usize) -> bool {
+            let index = self.divisors.iter().position(|&d| d == divisor).unwrap();
+            self.residues[index] == 0
+        }
+    }
+
     #[derive(Clone, Copy, Debug)]
     enum Test {
         Divisible(usize),
     }
 
     impl Test {
-        fn apply(&self, worry_level: usize) -> bool {
+        fn apply<W: Worry>(&self, worry_level: &W) -> bool {
             match self {
-                &Self::Divisible(d) => worry_level % d == 0,
+                &Self::Divisible(d) => worry_level.is_divisible_by(d),
+            }
+        }
+
+        fn divisor(&self) -> usize {
+            match self {
+                &Self::Divisible(d) => d,
             }
         }
     }
@@ -45,11 +322,223 @@ mod tests {
     #[derive(Clone, Debug)]
     struct Monkey {
         item_worry_levels: Vec<usize>,
-        op: Op,
+        op: Expr,
         test: Test,
         positive_target: usize,
         negative_target: usize,
-        inspection_count: usize,
+    }
+
+    #[derive(Debug, PartialEq, Eq)]
+    enum NotesError {
+        MissingHeader {
+            line: String,
+        },
+        UnexpectedMonkeyId {
+            expected: usize,
+            found: String,
+        },
+        UnknownLine {
+            monkey: usize,
+            line: String,
+        },
+        DuplicateField {
+            monkey: usize,
+            field: &'static str,
+        },
+        MissingField {
+            monkey: usize,
+            field: &'static str,
+        },
+        InvalidNumber {
+            monkey: usize,
+            text: String,
+        },
+        InvalidOperation {
+            monkey: usize,
+            error: ExpressionError,
+        },
+        InvalidDivisor {
+            monkey: usize,
+        },
+        TargetOutOfRange {
+            monkey: usize,
+            target: usize,
+            monkey_count: usize,
+        },
+    }
+
+    impl fmt::Display for NotesError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                NotesError::MissingHeader { line } => {
+                    write!(f, "expected a 'Monkey n:' header, found '{line}'")
+                }
+                NotesError::UnexpectedMonkeyId { expected, found } => {
+                    write!(f, "expected monkey {expected}, found monkey '{found}'")
+                }
+                NotesError::UnknownLine { monkey, line } => {
+                    write!(f, "monkey {monkey}: unknown line '{line}'")
+                }
+                NotesError::DuplicateField { monkey, field } => {
+                    write!(f, "monkey {monkey}: '{field}' appears more than once")
+                }
+                NotesError::MissingField { monkey, field } => {
+                    write!(f, "monkey {monkey}: missing '{field}'")
+                }
+                NotesError::InvalidNumber { monkey, text } => {
+                    write!(f, "monkey {monkey}: '{text}' is not a number")
+                }
+                NotesError::InvalidOperation { monkey, error } => {
+                    write!(f, "monkey {monkey}: invalid operation: {error}")
+                }
+                NotesError::InvalidDivisor { monkey } => {
+                    write!(f, "monkey {monkey}: can't test for divisibility by zero")
+                }
+                NotesError::TargetOutOfRange {
+                    monkey,
+                    target,
+                    monkey_count,
+                } => write!(
+                    f,
+                    "monkey {monkey} throws to monkey {target}, but there are only {monkey_count} monkeys"
+                ),
+            }
+        }
+    }
+
+    impl Error for NotesError {}
+
+    const STARTING_ITEMS: &str = "Starting items:";
+    const OPERATION: &str = "Operation:";
+    const TEST: &str = "Test:";
+    const IF_TRUE: &str = "If true:";
+    const IF_FALSE: &str = "If false:";
+
+    fn parse_number(monkey: usize, text: &str) -> Result<usize, NotesError> {
+        text.trim().parse().map_err(|_| NotesError::InvalidNumber {
+            monkey,
+            text: text.trim().to_string(),
+        })
+    }
+
+    /// Parses the notes on the monkeys. Indentation, blank lines and the order of the lines
+    /// within a monkey don't matter.
+    fn parse_notes(input: &str) -> Result<Vec<Monkey>, NotesError> {
+        let mut lines = input
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .peekable();
+        let mut monkeys = Vec::new();
+
+        while let Some(header) = lines.next() {
+            let index = monkeys.len();
+            let id = header
+                .strip_prefix("Monkey ")
+                .and_then(|rest| rest.strip_suffix(':'))
+                .ok_or_else(|| NotesError::MissingHeader {
+                    line: header.to_string(),
+                })?;
+            if id.trim().parse() != Ok(index) {
+                Err(NotesError::UnexpectedMonkeyId {
+                    expected: index,
+                    found: id.to_string(),
+                })?
+            }
+
+            let mut fields: [Option<&str>; 5] = [None; 5];
+            let names = [STARTING_ITEMS, OPERATION, TEST, IF_TRUE, IF_FALSE];
+            while let Some(line) = lines.next_if(|line| !line.starts_with("Monkey ")) {
+                let (field, value) = names
+                    .iter()
+                    .enumerate()
+                    .find_map(|(i, name)| line.strip_prefix(name).map(|value| (i, value)))
+                    .ok_or_else(|| NotesError::UnknownLine {
+                        monkey: index,
+                        line: line.to_string(),
+                    })?;
+                if fields[field].replace(value.trim()).is_some() {
+                    Err(NotesError::DuplicateField {
+                        monkey: index,
+                        field: names[field],
+                    })?
+                }
+            }
+            let field = |i: usize| {
+                fields[i].ok_or(NotesError::MissingField {
+                    monkey: index,
+                    field: names[i],
+                })
+            };
+
+            let items = field(0)?;
+            let item_worry_levels = if items.is_empty() {
+                Vec::new()
+            } else {
+                items
+                    .split(',')
+                    .map(|item| parse_number(index, item))
+                    .collect::<Result<_, _>>()?
+            };
+
+            let op = field(1)?
+                .strip_prefix("new =")
+                .ok_or_else(|| NotesError::InvalidOperation {
+                    monkey: index,
+                    error: ExpressionError("expected 'new = ...'".to_string()),
+                })?
+                .parse()
+                .map_err(|error| NotesError::InvalidOperation {
+                    monkey: index,
+                    error,
+                })?;
+
+            let divisor =
+                field(2)?
+                    .strip_prefix("divisible by")
+                    .ok_or_else(|| NotesError::UnknownLine {
+                        monkey: index,
+                        line: format!("{TEST} {}", fields[2].unwrap()),
+                    })?;
+            let divisor = parse_number(index, divisor)?;
+            if divisor == 0 {
+                Err(NotesError::InvalidDivisor { monkey: index })?
+            }
+
+            let target = |i: usize| {
+                let value = field(i)?;
+                let target = value.strip_prefix("throw to monkey").ok_or_else(|| {
+                    NotesError::UnknownLine {
+                        monkey: index,
+                        line: format!("{} {value}", names[i]),
+                    }
+                })?;
+                parse_number(index, target)
+            };
+
+            monkeys.push(Monkey {
+                item_worry_levels,
+                op,
+                test: Test::Divisible(divisor),
+                positive_target: target(3)?,
+                negative_target: target(4)?,
+            });
+        }
+
+        let monkey_count = monkeys.len();
+        for (index, monkey) in monkeys.iter().enumerate() {
+            for target in [monkey.positive_target, monkey.negative_target] {
+                if target >= monkey_count {
+                    Err(NotesError::TargetOutOfRange {
+                        monkey: index,
+                        target,
+                        monkey_count,
+                    })?
+                }
+            }
+        }
+
+        Ok(monkeys)
     }
 
     #[test]
@@ -79,88 +568,360 @@ mod tests {
         Ok(())
     }
 
-    fn day11(
-        input: String,
-        worry_reduction: usize,
-        number_of_rounds: usize,
-    ) -> Result<usize, Box<dyn Error>> {
-        let mut monkeys = Vec::with_capacity(10);
-
-        for monkey_spec in input.split("\n\n") {
-            let mut lines = monkey_spec.lines();
-            lines
-                .next()
-                .expect("there should be a header, even if we ignore it");
-
-            let items = lines.next().unwrap()[STARTING_ITEMS_PREFIX.len()..]
-                .split(",")
-                .map(|s| s.trim().parse::<usize>().unwrap())
-                .collect_vec();
-
-            let operation_parts = lines.next().unwrap()[OPERATIONS_PREFIX.len()..]
-                .split_whitespace()
-                .collect_vec();
-            let op = match operation_parts[0].chars().nth(0).unwrap() {
-                '+' => Op::Add(operation_parts[1].parse().unwrap()),
-                '*' => match operation_parts[1] {
-                    "old" => Op::Square,
-                    _ => Op::Mul(operation_parts[1].parse().unwrap()),
-                },
-                _ => panic!("Unkown op {operation_parts:?}"),
-            };
-            let test = Test::Divisible(lines.next().unwrap()[TEST_PREFIX.len()..].parse().unwrap());
-            let positive_target: usize = lines.next().unwrap()[POSITIVE_ACTION_PREFIX.len()..]
-                .parse()
-                .unwrap();
-            let negative_target: usize = lines.next().unwrap()[NEGATIVE_ACTION_PREFIX.len()..]
-                .parse()
-                .unwrap();
+    #[test]
+    fn day11_many_rounds() -> Result<(), Box<dyn Error>> {
+        let monkeys = parse_notes(&get_input(11)?)?;
+        let divisors = divisors(&monkeys);
+        let counts = extrapolated_inspection_counts(&monkeys, 1_000_000_000_000, |w| {
+            LcmModular::new(w, &divisors)
+        })?;
+        let monkey_business_level: u128 = counts
+            .iter()
+            .sorted()
+            .rev()
+            .take(2)
+            .map(|&c| c as u128)
+            .product();
+        println!("Monkey business level after 10^12 rounds: {monkey_business_level}");
+        Ok(())
+    }
 
-            let monkey = Monkey {
-                item_worry_levels: items,
-                op,
-                test,
-                positive_target,
-                negative_target,
-                inspection_count: 0,
-            };
+    #[test]
+    fn worry_backends_agree() -> Result<(), Box<dyn Error>> {
+        let monkeys = parse_notes(&get_sample_input(11)?)?;
+        let divisors = divisors(&monkeys);
+        let shared_divisors: Rc<[usize]> = divisors.clone().into();
 
-            monkeys.push(RefCell::new(monkey));
+        // without relief the exact worry levels explode, so only compare a few rounds
+        for rounds in 0..=6 {
+            let exact = inspection_counts(&monkeys, 1, rounds, BigUint::from)?;
+            let lcm = inspection_counts(&monkeys, 1, rounds, |w| LcmModular::new(w, &divisors))?;
+            let residues = inspection_counts(&monkeys, 1, rounds, |w| {
+                Residues::new(w, shared_divisors.clone())
+            })?;
+            assert!(exact == lcm, "{rounds} rounds");
+            assert!(exact == residues, "{rounds} rounds");
         }
 
-        println!("Monkeys:\n{monkeys:?}");
+        let lcm = inspection_counts(&monkeys, 1, 1000, |w| LcmModular::new(w, &divisors))?;
+        let residues = inspection_counts(&monkeys, 1, 1000, |w| {
+            Residues::new(w, shared_divisors.clone())
+        })?;
+        assert!(lcm == residues);
+        assert!(lcm == vec![5204, 4792, 199, 5192]);
+
+        // with relief, only exact arithmetic is valid
+        let exact = inspection_counts(&monkeys, 3, 20, BigUint::from)?;
+        assert!(exact == vec![101, 95, 7, 105]);
+        assert!(inspection_counts(&monkeys, 3, 20, |w| LcmModular::new(w, &divisors)).is_err());
+        assert!(inspection_counts(&monkeys, 3, 20, |w| {
+            Residues::new(w, shared_divisors.clone())
+        })
+        .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn extrapolated_inspections() -> Result<(), Box<dyn Error>> {
+        let monkeys = parse_notes(&get_sample_input(11)?)?;
+        let divisors = divisors(&monkeys);
+        let shared_divisors: Rc<[usize]> = divisors.clone().into();
+
+        for rounds in (0..=50).chain([1000, 10_000]) {
+            let simulated =
+                inspection_counts(&monkeys, 1, rounds, |w| LcmModular::new(w, &divisors))?;
+            let extrapolated = extrapolated_inspection_counts(&monkeys, rounds as u64, |w| {
+                LcmModular::new(w, &divisors)
+            })?;
+            assert!(
+                simulated.iter().map(|&c| c as u64).collect_vec() == extrapolated,
+                "{rounds} rounds"
+            );
+        }
+
+        let counts = extrapolated_inspection_counts(&monkeys, 10_000, |w| {
+            Residues::new(w, shared_divisors.clone())
+        })?;
+        assert!(counts == vec![52166, 47830, 1938, 52013]);
+
+        let counts = extrapolated_inspection_counts(&monkeys, 1_000_000_000_000, |w| {
+            LcmModular::new(w, &divisors)
+        })?;
+        println!("after 10^12 rounds: {counts:?}");
+        assert!(counts.iter().sum::<u64>() > 1_000_000_000_000);
+
+        Ok(())
+    }
+
+    #[test]
+    fn expressions() -> Result<(), Box<dyn Error>> {
+        let cases = [
+            ("old + old", 10),
+            ("old * old", 25),
+            ("3 * old", 15),
+            ("old - 2", 3),
+            ("old / 2", 2),
+            ("20 / old", 4),
+            ("old * 2 + 1", 11),
+            ("1 + old * 2", 11),
+            ("(1 + old) * 2", 12),
+            ("old - 1 - 1", 3),
+        ];
+        for (text, expected) in cases {
+            let expr: Expr = text.parse()?;
+            assert!(expr.evaluate(&5) == Some(expected), "{text}");
+        }
+
+        assert!("old - 6".parse::<Expr>()?.evaluate(&5usize).is_none());
+        assert!("old / 0".parse::<Expr>()?.evaluate(&5usize).is_none());
+        for invalid in ["", "old +", "old % 2", "(old", "old old", "new"] {
+            assert!(invalid.parse::<Expr>().is_err(), "{invalid}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn notes_parsing() -> Result<(), Box<dyn Error>> {
+        let sample = get_sample_input(11)?;
+        let reference = parse_notes(&sample)?;
+        assert!(reference.len() == 4);
+        assert!(reference[2].op == "old * old".parse()?);
+
+        // indentation and line order don't matter
+        let reordered = "Monkey 0:\n\
+            Operation: new = old + old\n\
+            \tStarting items: 1, 2\n\
+            If false: throw to monkey 1\n\
+            If true: throw to monkey 1\n\
+            Test: divisible by 2\n\
+            \n\
+            Monkey 1:\n\
+            Starting items:\n\
+            Operation: new = old * 3\n\
+            Test: divisible by 3\n\
+            If true: throw to monkey 0\n\
+            If false: throw to monkey 0\n";
+        let monkeys = parse_notes(reordered)?;
+        assert!(monkeys[0].item_worry_levels == vec![1, 2]);
+        assert!(monkeys[0].op.evaluate(&4) == Some(8));
+        assert!(monkeys[1].item_worry_levels.is_empty());
+
+        assert!(matches!(
+            parse_notes(&sample.replace("throw to monkey 3", "throw to monkey 4")),
+            Err(NotesError::TargetOutOfRange {
+                monkey: 0,
+                target: 4,
+                monkey_count: 4
+            })
+        ));
+        assert!(matches!(
+            parse_notes(&sample.replace("divisible by 23", "divisible by 0")),
+            Err(NotesError::InvalidDivisor { monkey: 0 })
+        ));
+        assert!(matches!(
+            parse_notes(&sample.replace("old * 19", "old ^ 19")),
+            Err(NotesError::InvalidOperation { monkey: 0, .. })
+        ));
+        assert!(matches!(
+            parse_notes(&sample.replace("  Test: divisible by 13\n", "")),
+            Err(NotesError::MissingField {
+                monkey: 2,
+                field: TEST
+            })
+        ));
+        assert!(matches!(
+            parse_notes(&sample.replace("Monkey 1:", "Monkey 7:")),
+            Err(NotesError::UnexpectedMonkeyId { expected: 1, .. })
+        ));
+        assert!(matches!(
+            parse_notes(&sample.replace("79, 98", "79, x")),
+            Err(NotesError::InvalidNumber { .. })
+        ));
+        Ok(())
+    }
 
-        let lcm = monkeys
+    /// Plays the rounds, returning how many items every monkey inspected.
+    fn inspection_counts<W: Worry>(
+        monkeys: &[Monkey],
+        worry_reduction: usize,
+        number_of_rounds: usize,
+        worry: impl Fn(usize) -> W,
+    ) -> Result<Vec<usize>, Box<dyn Error>> {
+        let mut items = monkeys
             .iter()
-            .map(|monkey| monkey.borrow().test)
-            .map(|test| match test {
-                Test::Divisible(d) => d,
+            .map(|monkey| {
+                monkey
+                    .item_worry_levels
+                    .iter()
+                    .map(|&w| worry(w))
+                    .collect_vec()
             })
-            .product::<usize>();
+            .collect_vec();
+        let mut inspection_counts = vec![0; monkeys.len()];
 
         for _ in 0..number_of_rounds {
-            for monkey_cell in &monkeys {
-                let mut monkey = monkey_cell.borrow_mut();
-                let old_worry_levels = monkey.item_worry_levels.drain(0..).collect_vec();
-                for item in old_worry_levels {
-                    monkey.inspection_count += 1;
-                    let new = (monkey.op.execute(item) / worry_reduction) % lcm;
-                    let target_monkey = match monkey.test.apply(new) {
-                        true => &monkeys[monkey.positive_target],
-                        false => &monkeys[monkey.negative_target],
+            for (index, monkey) in monkeys.iter().enumerate() {
+                for item in std::mem::take(&mut items[index]) {
+                    inspection_counts[index] += 1;
+                    let new = monkey
+                        .op
+                        .evaluate(&item)
+                        .and_then(|new| new.relieve(worry_reduction))
+                        .ok_or_else(|| format!("Can't evaluate {:?} for {item:?}", monkey.op))?;
+                    let target = match monkey.test.apply(&new) {
+                        true => monkey.positive_target,
+                        false => monkey.negative_target,
                     };
-                    target_monkey.borrow_mut().item_worry_levels.push(new);
+                    items[target].push(new);
                 }
             }
         }
 
-        let sorted_counts = monkeys
-            .iter()
-            .map(|monkey_cell| monkey_cell.borrow().inspection_count)
-            .sorted()
-            .rev()
-            .collect_vec();
-        let monkey_business_level = sorted_counts[0] * sorted_counts[1];
-        Ok(monkey_business_level)
+        Ok(inspection_counts)
+    }
+
+    /// Follows a single item through one round, starting at the monkey that holds it.
+    ///
+    /// An item thrown to a monkey further down the line is inspected again in the same round,
+    /// one thrown back (or to the same monkey) waits for the next round.
+    fn item_round<W: Worry>(
+        monkeys: &[Monkey],
+        mut holder: usize,
+        mut item: W,
+        inspected: &mut [u64],
+    ) -> Result<(usize, W), Box<dyn Error>> {
+        loop {
+            let monkey = &monkeys[holder];
+            inspected[holder] += 1;
+            let new = monkey
+                .op
+                .evaluate(&item)
+                .ok_or_else(|| format!("Can't evaluate {:?} for {item:?}", monkey.op))?;
+            let target = match monkey.test.apply(&new) {
+                true => monkey.positive_target,
+                false => monkey.negative_target,
+            };
+            if target <= holder {
+                return Ok((target, new));
+            }
+            holder = target;
+            item = new;
+        }
+    }
+
+    /// The inspections caused by a single item, as one of its trajectories through the monkeys.
+    ///
+    /// Without relief and with a bounded worry representation, the state of an item at the start
+    /// of a round (its holder and its worry level) can only take finitely many values, so the
+    /// trajectory always ends up in a cycle.
+    struct ItemTrajectory {
+        monkey_count: usize,
+        /// The inspections per monkey after every round, up to and including the first repeat.
+        cumulative: Vec<u64>,
+        cycle_start: usize,
+        cycle_length: usize,
+    }
+
+    impl ItemTrajectory {
+        fn trace<W: Worry + Hash + Eq>(
+            monkeys: &[Monkey],
+            holder: usize,
+            item: W,
+        ) -> Result<ItemTrajectory, Box<dyn Error>> {
+            let monkey_count = monkeys.len();
+            let mut seen = HashMap::new();
+            let mut cumulative = vec![0; monkey_count];
+            let mut state = (holder, item);
+            let mut round = 0;
+            let cycle_start = loop {
+                if let Some(&start) = seen.get(&state) {
+                    break start;
+                }
+                let mut inspected = cumulative[round * monkey_count..].to_vec();
+                let (holder, item) = state.clone();
+                seen.insert(state, round);
+                state = item_round(monkeys, holder, item, &mut inspected)?;
+                cumulative.extend(inspected);
+                round += 1;
+            };
+            Ok(ItemTrajectory {
+                monkey_count,
+                cumulative,
+                cycle_start,
+                cycle_length: round - cycle_start,
+            })
+        }
+
+        fn after(&self, round: usize) -> &[u64] {
+            &self.cumulative[round * self.monkey_count..(round + 1) * self.monkey_count]
+        }
+
+        /// Adds the inspections during the first `number_of_rounds` rounds to `counts`.
+        fn add_inspections(&self, number_of_rounds: u64, counts: &mut [u64]) {
+            let traced = (self.cycle_start + self.cycle_length) as u64;
+            if number_of_rounds <= traced {
+                for (count, n) in counts.iter_mut().zip(self.after(number_of_rounds as usize)) {
+                    *count += n;
+                }
+                return;
+            }
+            let (cycles, remainder) =
+                (number_of_rounds - self.cycle_start as u64).div_rem(&(self.cycle_length as u64));
+            let start = self.after(self.cycle_start);
+            let end = self.after(self.cycle_start + self.cycle_length);
+            let partial = self.after(self.cycle_start + remainder as usize);
+            for (monkey, count) in counts.iter_mut().enumerate() {
+                *count += partial[monkey] + cycles * (end[monkey] - start[monkey]);
+            }
+        }
+    }
+
+    /// The same as `inspection_counts` without relief, but for any number of rounds.
+    ///
+    /// Items never interact, so rather than playing all the rounds, this follows every item on
+    /// its own until its trajectory repeats, and extrapolates the inspections from its cycle.
+    fn extrapolated_inspection_counts<W: Worry + Hash + Eq>(
+        monkeys: &[Monkey],
+        number_of_rounds: u64,
+        worry: impl Fn(usize) -> W,
+    ) -> Result<Vec<u64>, Box<dyn Error>> {
+        let mut counts = vec![0; monkeys.len()];
+        for (holder, monkey) in monkeys.iter().enumerate() {
+            for &item in &monkey.item_worry_levels {
+                ItemTrajectory::trace(monkeys, holder, worry(item))?
+                    .add_inspections(number_of_rounds, &mut counts);
+            }
+        }
+        Ok(counts)
+    }
+
+    fn monkey_business_level(inspection_counts: &[usize]) -> usize {
+        inspection_counts.iter().sorted().rev().take(2).product()
+    }
+
+    fn divisors(monkeys: &[Monkey]) -> Vec<usize> {
+        monkeys.iter().map(|monkey| monkey.test.divisor()).collect()
+    }
+
+    fn day11(
+        input: String,
+        worry_reduction: usize,
+        number_of_rounds: usize,
+    ) -> Result<usize, Box<dyn Error>> {
+        let monkeys = parse_notes(&input)?;
+        println!("Monkeys:\n{monkeys:?}");
+
+        // relief doesn't commute with modular arithmetic, so it needs the exact worry levels
+        let counts = if worry_reduction == 1 {
+            let divisors = divisors(&monkeys);
+            inspection_counts(&monkeys, worry_reduction, number_of_rounds, |w| {
+                LcmModular::new(w, &divisors)
+            })?
+        } else {
+            inspection_counts(&monkeys, worry_reduction, number_of_rounds, BigUint::from)?
+        };
+
+        Ok(monkey_business_level(&counts))
     }
 }
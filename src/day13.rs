@@ -1,11 +1,226 @@
+use std::{cmp::Ordering, error::Error, fmt, iter::Peekable, str::FromStr};
+
+use itertools::Itertools;
+use num_bigint::BigUint;
+
+/// A packet is either an integer or a list of packets.
+///
+/// Equality follows the ordering of the puzzle, so `4` and `[4]` are equal packets.
+#[derive(Clone, Debug)]
+enum Packet {
+    Integer(BigUint),
+    List(Vec<Packet>),
+}
+
+#[derive(Debug)]
+struct ParsePacketError(String);
+
+impl fmt::Display for ParsePacketError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid packet: {}", self.0)
+    }
+}
+
+impl Error for ParsePacketError {}
+
+impl Packet {
+    fn parse<I: Iterator<Item = char>>(
+        chars: &mut Peekable<I>,
+    ) -> Result<Packet, ParsePacketError> {
+        match chars.peek() {
+            Some('[') => {
+                chars.next();
+                let mut items = Vec::new();
+                if chars.peek() == Some(&']') {
+                    chars.next();
+                    return Ok(Packet::List(items));
+                }
+                loop {
+                    items.push(Packet::parse(chars)?);
+                    match chars.next() {
+                        Some(',') => continue,
+                        Some(']') => return Ok(Packet::List(items)),
+                        Some(c) => Err(ParsePacketError(format!("unexpected '{c}' in list")))?,
+                        None => Err(ParsePacketError("unclosed list".to_string()))?,
+                    }
+                }
+            }
+            Some(c) if c.is_ascii_digit() => {
+                let mut digits = String::new();
+                while let Some(c) = chars.next_if(|c| c.is_ascii_digit()) {
+                    digits.push(c);
+                }
+                Ok(Packet::Integer(digits.parse().unwrap()))
+            }
+            Some(c) => Err(ParsePacketError(format!("unexpected '{c}'"))),
+            None => Err(ParsePacketError("unexpected end of packet".to_string())),
+        }
+    }
+}
+
+impl FromStr for Packet {
+    type Err = ParsePacketError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chars = s.trim().chars().peekable();
+        let packet = Packet::parse(&mut chars)?;
+        match chars.next() {
+            None => Ok(packet),
+            Some(c) => Err(ParsePacketError(format!(
+                "unexpected '{c}' after the packet"
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for Packet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Packet::Integer(value) => write!(f, "{value}"),
+            Packet::List(items) => write!(f, "[{}]", items.iter().join(",")),
+        }
+    }
+}
+
+impl Ord for Packet {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Packet::Integer(left), Packet::Integer(right)) => left.cmp(right),
+            (Packet::List(left), Packet::List(right)) => left.cmp(right),
+            (Packet::Integer(_), Packet::List(right)) => {
+                std::slice::from_ref(self).cmp(right.as_slice())
+            }
+            (Packet::List(left), Packet::Integer(_)) => {
+                left.as_slice().cmp(std::slice::from_ref(other))
+            }
+        }
+    }
+}
+
+impl PartialOrd for Packet {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Packet {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Packet {}
+
+/// Compares two packets like `Ord` does, but also returns the steps of the comparison,
+/// in the nested format the puzzle description uses.
+fn compare_traced(left: &Packet, right: &Packet) -> (Ordering, String) {
+    let mut trace = String::new();
+    let ordering = trace_packets(left, right, 0, &mut trace);
+    (ordering, trace)
+}
+
+fn trace_line(trace: &mut String, depth: usize, line: &str) {
+    trace.push_str(&"  ".repeat(depth));
+    trace.push_str("- ");
+    trace.push_str(line);
+    trace.push('\n');
+}
+
+fn trace_packets(left: &Packet, right: &Packet, depth: usize, trace: &mut String) -> Ordering {
+    trace_line(trace, depth, &format!("Compare {left} vs {right}"));
+    let depth = depth + 1;
+
+    match (left, right) {
+        (Packet::Integer(l), Packet::Integer(r)) => {
+            let ordering = l.cmp(r);
+            match ordering {
+                Ordering::Less => trace_line(
+                    trace,
+                    depth,
+                    "Left side is smaller, so inputs are in the right order",
+                ),
+                Ordering::Greater => trace_line(
+                    trace,
+                    depth,
+                    "Right side is smaller, so inputs are not in the right order",
+                ),
+                Ordering::Equal => {}
+            }
+            ordering
+        }
+        (Packet::Integer(_), Packet::List(_)) => {
+            let converted = Packet::List(vec![left.clone()]);
+            trace_line(
+                trace,
+                depth,
+                &format!("Mixed types; convert left to {converted} and retry comparison"),
+            );
+            trace_packets(&converted, right, depth, trace)
+        }
+        (Packet::List(_), Packet::Integer(_)) => {
+            let converted = Packet::List(vec![right.clone()]);
+            trace_line(
+                trace,
+                depth,
+                &format!("Mixed types; convert right to {converted} and retry comparison"),
+            );
+            trace_packets(left, &converted, depth, trace)
+        }
+        (Packet::List(l), Packet::List(r)) => {
+            for (l, r) in l.iter().zip(r.iter()) {
+                let ordering = trace_packets(l, r, depth, trace);
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            let ordering = l.len().cmp(&r.len());
+            match ordering {
+                Ordering::Less => trace_line(
+                    trace,
+                    depth,
+                    "Left side ran out of items, so inputs are in the right order",
+                ),
+                Ordering::Greater => trace_line(
+                    trace,
+                    depth,
+                    "Right side ran out of items, so inputs are not in the right order",
+                ),
+                Ordering::Equal => {}
+            }
+            ordering
+        }
+    }
+}
+
+/// Prints the comparison of two packets given on the command line.
+pub fn compare_command(left: &str, right: &str) -> Result<(), Box<dyn Error>> {
+    let (ordering, trace) = compare_traced(&left.parse()?, &right.parse()?);
+    print!("{trace}");
+    match ordering {
+        Ordering::Less => println!("The packets are in the right order"),
+        Ordering::Equal => println!("The packets are equal"),
+        Ordering::Greater => println!("The packets are not in the right order"),
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use std::{cmp::Ordering, error::Error, iter::Peekable};
 
     use itertools::Itertools;
 
+    use super::{compare_traced, Packet, ParsePacketError};
     use crate::common::tests::{get_input, get_sample_input};
 
+    fn parse_packets(input: &str) -> Result<Vec<Packet>, ParsePacketError> {
+        input
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| line.parse())
+            .collect()
+    }
+
     #[test]
     fn day13_part1_sample() -> Result<(), Box<dyn Error>> {
         let indices_sum = day13_part1(get_sample_input(13)?)?;
@@ -20,46 +235,132 @@ mod tests {
     }
 
     fn day13_part1(input: String) -> Result<usize, Box<dyn Error>> {
-        let mut sum = 0;
-        for (pair_index, part) in input.split("\n\n").enumerate() {
-            let pair_index = pair_index + 1;
-            let mut lines = part.lines();
-            let mut left = lines.next().unwrap().chars().peekable();
-            let mut right = lines.next().unwrap().chars().peekable();
-
-            assert!(left.next().unwrap() == '[');
-            assert!(right.next().unwrap() == '[');
-
-            match compare_lists(&mut left, &mut right) {
-                Ordering::Less => {
-                    sum += pair_index;
-                }
-                Ordering::Equal => panic!(),
-                Ordering::Greater => {
-                    // OK, do nothing
-                }
-            }
-        }
+        let packets = parse_packets(&input)?;
+        let sum = packets
+            .iter()
+            .tuples()
+            .enumerate()
+            .filter(|(_, (left, right))| left < right)
+            .map(|(pair_index, _)| pair_index + 1)
+            .sum();
 
         Ok(sum)
     }
 
+    fn decoder_key(input: &str) -> Result<usize, Box<dyn Error>> {
+        let dividers: [Packet; 2] = ["[[2]]".parse()?, "[[6]]".parse()?];
+        let mut packets = parse_packets(input)?;
+        packets.extend(dividers.iter().cloned());
+        packets.sort();
+
+        // compare the text as well: `[[2]]` and `[2]` are equal packets
+        let product = dividers
+            .iter()
+            .map(|divider| {
+                packets
+                    .iter()
+                    .position(|p| p.to_string() == divider.to_string())
+                    .unwrap()
+                    + 1
+            })
+            .product();
+
+        Ok(product)
+    }
+
+    #[test]
+    fn day13_part2_sample() -> Result<(), Box<dyn Error>> {
+        let product = decoder_key(&get_sample_input(13)?)?;
+        assert!(product == 140);
+        Ok(())
+    }
+
     #[test]
     fn day13_part2() -> Result<(), Box<dyn Error>> {
-        let input = get_input(13)?;
-        let input = input + "\n[[2]]\n[[6]]\n";
-        let packets = input
+        let product = decoder_key(&get_input(13)?)?;
+        println!("Product: {product}");
+
+        Ok(())
+    }
+
+    #[test]
+    fn packet_parsing() -> Result<(), Box<dyn Error>> {
+        for text in [
+            "[]",
+            "[[]]",
+            "[1,[2,[3,[4,[5,6,7]]]],8,9]",
+            "[10,[100],1000]",
+        ] {
+            assert!(text.parse::<Packet>()?.to_string() == text);
+        }
+        let big: Packet = "[123456789012345678901234567890]".parse()?;
+        assert!(big > "[123456789012345678901234567889]".parse()?);
+        assert!("[100]".parse::<Packet>()? > "[99]".parse()?);
+        assert!("[[1],[2,3,4]]".parse::<Packet>()? == "[1,[2,3,4]]".parse()?);
+
+        for invalid in ["", "[", "[1,]", "[1]]", "[a]", "[1 2]"] {
+            assert!(invalid.parse::<Packet>().is_err());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn packet_ordering_matches_streaming_comparison() -> Result<(), Box<dyn Error>> {
+        let input = get_sample_input(13)?;
+        let mut texts = input
             .lines()
             .filter(|line| !line.is_empty())
-            .sorted_by(|left, right| compare_strings(left, right))
+            .map(|line| line.to_string())
             .collect_vec();
+        texts.extend(["[[2]]", "[[6]]", "[4,8]", "[[5],8]", "[4,[9,9]]"].map(String::from));
+
+        for (left, right) in texts.iter().cartesian_product(texts.iter()) {
+            let left_packet: Packet = left.parse()?;
+            let right_packet: Packet = right.parse()?;
+            assert!(
+                left_packet.cmp(&right_packet) == compare_strings(left, right),
+                "{left} vs {right}"
+            );
+        }
+        Ok(())
+    }
 
-        let pos_2 = packets.iter().position(|s| s == &"[[2]]").unwrap() + 1;
-        let pos_6 = packets.iter().position(|s| s == &"[[6]]").unwrap() + 1;
+    #[test]
+    fn comparison_trace() -> Result<(), Box<dyn Error>> {
+        let (ordering, trace) = compare_traced(&"[[1],[2,3,4]]".parse()?, &"[[1],4]".parse()?);
+        assert!(ordering == Ordering::Less);
+        assert!(
+            trace
+                == "\
+- Compare [[1],[2,3,4]] vs [[1],4]
+  - Compare [1] vs [1]
+    - Compare 1 vs 1
+  - Compare [2,3,4] vs 4
+    - Mixed types; convert right to [4] and retry comparison
+    - Compare [2,3,4] vs [4]
+      - Compare 2 vs 4
+        - Left side is smaller, so inputs are in the right order
+"
+        );
 
-        let product = pos_2 * pos_6;
-        println!("Product: {product}");
+        let (ordering, trace) = compare_traced(&"[[[]]]".parse()?, &"[[]]".parse()?);
+        assert!(ordering == Ordering::Greater);
+        assert!(
+            trace
+                == "\
+- Compare [[[]]] vs [[]]
+  - Compare [[]] vs []
+    - Right side ran out of items, so inputs are not in the right order
+"
+        );
 
+        let input = get_sample_input(13)?;
+        for (left, right) in input.lines().filter(|l| !l.is_empty()).tuples() {
+            let (left, right): (Packet, Packet) = (left.parse()?, right.parse()?);
+            let (ordering, trace) = compare_traced(&left, &right);
+            assert!(ordering == left.cmp(&right));
+            assert!(trace.starts_with(&format!("- Compare {left} vs {right}\n")));
+        }
         Ok(())
     }
 
@@ -98,6 +399,9 @@ mod tests {
         assert!(compare_strings("[4,8]", "[[3],8]") == Ordering::Greater);
     }
 
+    // The original streaming comparison, only kept to check the `Packet` ordering against.
+    // It only supports integers up to 99.
+
     fn compare_strings(left: &str, right: &str) -> Ordering {
         let mut left = left.chars().peekable();
         let mut right = right.chars().peekable();
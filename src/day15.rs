@@ -1,6 +1,8 @@
 #[cfg(test)]
 mod tests {
-    use std::{collections::HashSet, error::Error, ops::RangeInclusive, str::FromStr};
+    use std::{
+        collections::HashSet, error::Error, ops::RangeInclusive, str::FromStr, time::Instant,
+    };
 
     use itertools::Itertools;
     use num_bigint::{BigInt, ToBigInt};
@@ -75,13 +77,91 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn day15_part2_sample_geometric() -> Result<(), Box<dyn Error>> {
+        let input = get_sample_input(15)?;
+        let search_space = 20;
+
+        let pairs = parse_input(&input)?;
+        let row_scan = search_for_open_beacon_positions(&pairs, search_space);
+        let geometric = find_open_beacon_positions_geometric(&pairs, search_space);
+
+        assert!(geometric == row_scan);
+
+        // every position is covered when looking at a smaller part of the sample
+        assert!(find_open_beacon_positions_geometric(&pairs, 10).is_empty());
+        assert!(search_for_open_beacon_positions(&pairs, 10).is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn day15_covered_area_sample() -> Result<(), Box<dyn Error>> {
+        let input = get_sample_input(15)?;
+        let pairs = parse_input(&input)?;
+
+        let covered = |x: i32, y: i32| {
+            pairs
+                .iter()
+                .any(|((sx, sy), _, d)| (x - sx).abs() + (y - sy).abs() <= *d)
+        };
+        let brute_force = |(x0, y0): Coord, (x1, y1): Coord| {
+            (x0..=x1)
+                .cartesian_product(y0..=y1)
+                .filter(|&(x, y)| covered(x, y))
+                .count() as i64
+        };
+
+        assert!(covered_area(&pairs, None) == brute_force((-20, -20), (40, 40)));
+        // everything in the search space, except for the distress beacon
+        assert!(covered_area(&pairs, Some(((0, 0), (20, 20)))) == 21 * 21 - 1);
+        // part 1 counts the same row, but without the beacon on it
+        assert!(covered_area(&pairs, Some(((-100, 10), (100, 10)))) == 26 + 1);
+        for clip in [((3, -4), (17, 9)), ((-6, 5), (2, 30)), ((10, 10), (10, 10))] {
+            assert!(covered_area(&pairs, Some(clip)) == brute_force(clip.0, clip.1));
+        }
+
+        let svg = to_svg(&pairs, Some(((0, 0), (20, 20))), &[(14, 11)]);
+        assert!(svg.matches("<polygon").count() == pairs.len());
+
+        Ok(())
+    }
+
+    #[test]
+    fn day15_covered_area_full() -> Result<(), Box<dyn Error>> {
+        let input = get_input(15)?;
+        let search_space = 4_000_000;
+
+        let pairs = parse_input(&input)?;
+        println!("covered positions: {}", covered_area(&pairs, None));
+        println!(
+            "covered positions in the search space: {}",
+            covered_area(&pairs, Some(((0, 0), (search_space, search_space))))
+        );
+
+        let open = find_open_beacon_positions_geometric(&pairs, search_space)
+            .into_iter()
+            .map(|(position, _)| position)
+            .collect_vec();
+        let path = std::env::temp_dir().join("day15.svg");
+        std::fs::write(
+            &path,
+            to_svg(&pairs, Some(((0, 0), (search_space, search_space))), &open),
+        )?;
+        println!("rendered the sensor coverage to {path:?}");
+
+        Ok(())
+    }
+
     #[test]
     fn day15_part2_full() -> Result<(), Box<dyn Error>> {
         let input = get_input(15)?;
         let search_space = 4_000_000;
 
         let pairs = parse_input(&input)?;
-        let possible_locations = search_for_open_beacon_positions(&pairs, search_space);
+        let start = Instant::now();
+        let possible_locations = find_open_beacon_positions_geometric(&pairs, search_space);
+        println!("found in {:?}", start.elapsed());
 
         for location in possible_locations {
             let (x, y) = location.0;
@@ -148,6 +228,221 @@ mod tests {
         possible_locations
     }
 
+    /// Finds the positions that are not covered by any sensor without scanning rows.
+    ///
+    /// In rotated coordinates (u = x + y, v = x - y) every sensor range is an axis aligned square.
+    /// An isolated open position lies right outside the range of the sensors around it, so it is on
+    /// a u-line and a v-line of the just-out-of-range boundaries, or on the edge of the search space.
+    /// Only those intersections get checked.
+    fn find_open_beacon_positions_geometric(
+        pairs: &[(Coord, Coord, Ordinal)],
+        search_space: i32,
+    ) -> Vec<(Coord, BigInt)> {
+        let mut u_lines = Vec::new();
+        let mut v_lines = Vec::new();
+        for ((sx, sy), _, manhattan_distance) in pairs {
+            let (u, v) = (sx + sy, sx - sy);
+            let reach = manhattan_distance + 1;
+            u_lines.extend([u - reach, u + reach]);
+            v_lines.extend([v - reach, v + reach]);
+        }
+
+        let mut candidates = vec![
+            (0, 0),
+            (0, search_space),
+            (search_space, 0),
+            (search_space, search_space),
+        ];
+        for &u in &u_lines {
+            for &v in &v_lines {
+                if (u + v) % 2 == 0 {
+                    candidates.push(((u + v) / 2, (u - v) / 2));
+                }
+            }
+        }
+        for edge in [0, search_space] {
+            for &u in &u_lines {
+                candidates.extend([(edge, u - edge), (u - edge, edge)]);
+            }
+            for &v in &v_lines {
+                candidates.extend([(edge, edge - v), (v + edge, edge)]);
+            }
+        }
+
+        candidates
+            .into_iter()
+            .filter(|(x, y)| (0..=search_space).contains(x) && (0..=search_space).contains(y))
+            .filter(|(x, y)| {
+                pairs.iter().all(|((sx, sy), _, manhattan_distance)| {
+                    (x - sx).abs() + (y - sy).abs() > *manhattan_distance
+                })
+            })
+            .sorted_by_key(|&(x, y)| (y, x))
+            .dedup()
+            .map(|(x, y)| ((x, y), tuning_frequency(x, y)))
+            .collect()
+    }
+
+    /// The corners of the smallest box containing the ranges of all sensors.
+    fn coverage_bounds(pairs: &[(Coord, Coord, Ordinal)]) -> (Coord, Coord) {
+        let mut min = (Ordinal::MAX, Ordinal::MAX);
+        let mut max = (Ordinal::MIN, Ordinal::MIN);
+        for &((sx, sy), _, r) in pairs {
+            min = (min.0.min(sx - r), min.1.min(sy - r));
+            max = (max.0.max(sx + r), max.1.max(sy + r));
+        }
+        (min, max)
+    }
+
+    /// The number of positions covered by at least one sensor, optionally only counting the positions
+    /// in the box between two corners (inclusive).
+    ///
+    /// In rotated coordinates (u = x + y, v = x - y) the sensor ranges are squares, but only the (u, v)
+    /// with u and v of the same parity are positions. The u axis is cut into slabs, such that inside a
+    /// slab the union of the squares and the clipping box don't change shape. The number of positions
+    /// covered in a single u column is then linear in u (per parity), so every slab is summed exactly
+    /// from its first and last column.
+    fn covered_area(pairs: &[(Coord, Coord, Ordinal)], clip: Option<(Coord, Coord)>) -> i64 {
+        if pairs.is_empty() {
+            return 0;
+        }
+
+        let squares = pairs
+            .iter()
+            .map(|&((sx, sy), _, distance)| {
+                let (u, v, r) = ((sx + sy) as i64, (sx - sy) as i64, distance as i64);
+                (u - r, u + r, v - r, v + r)
+            })
+            .collect_vec();
+
+        let ((x0, y0), (x1, y1)) = clip.unwrap_or_else(|| coverage_bounds(pairs));
+        let ((x0, y0), (x1, y1)) = ((x0 as i64, y0 as i64), (x1 as i64, y1 as i64));
+        if x0 > x1 || y0 > y1 {
+            return 0;
+        }
+
+        // every u where a square starts or ends, or where an edge of a square or of the clipping
+        // box crosses an edge of the clipping box
+        let mut breakpoints = vec![x0 + y0, x0 + y1, x1 + y0, x1 + y1];
+        for &(u0, u1, v0, v1) in &squares {
+            breakpoints.extend([u0, u1 + 1]);
+            for v in [v0, v1] {
+                breakpoints.extend([2 * x0 - v, v + 2 * y1, 2 * x1 - v, v + 2 * y0]);
+            }
+        }
+        let breakpoints = breakpoints
+            .into_iter()
+            .flat_map(|b| [b - 1, b, b + 1])
+            .sorted()
+            .dedup()
+            .collect_vec();
+
+        let mut area = 0;
+        for (&start, &end) in breakpoints.iter().tuple_windows() {
+            let mut intervals: Vec<(i64, i64)> = Vec::new();
+            for &(_, _, v0, v1) in squares.iter().filter(|s| s.0 <= start && start <= s.1) {
+                intervals.push((v0, v1));
+            }
+            intervals.sort();
+            let mut merged: Vec<(i64, i64)> = Vec::new();
+            for (v0, v1) in intervals {
+                match merged.last_mut() {
+                    Some(last) if v0 <= last.1 + 1 => last.1 = last.1.max(v1),
+                    _ => merged.push((v0, v1)),
+                }
+            }
+            if merged.is_empty() {
+                continue;
+            }
+
+            // positions in column u: v with the parity of u, inside the union and inside the box
+            let column = |u: i64| -> i64 {
+                let clip_low = (2 * x0 - u).max(u - 2 * y1);
+                let clip_high = (2 * x1 - u).min(u - 2 * y0);
+                merged
+                    .iter()
+                    .map(|&(v0, v1)| {
+                        let (low, high) = (v0.max(clip_low), v1.min(clip_high));
+                        if low > high {
+                            0
+                        } else {
+                            (high - u).div_euclid(2) - (low - 1 - u).div_euclid(2)
+                        }
+                    })
+                    .sum()
+            };
+
+            for first in [start, start + 1] {
+                if first >= end {
+                    continue;
+                }
+                let last = first + (end - 1 - first) / 2 * 2;
+                let count = (last - first) / 2 + 1;
+                area += (column(first) + column(last)) * count / 2;
+            }
+        }
+
+        area
+    }
+
+    /// Renders the sensor ranges, sensors and beacons as an SVG, with a few positions highlighted.
+    fn to_svg(
+        pairs: &[(Coord, Coord, Ordinal)],
+        clip: Option<(Coord, Coord)>,
+        highlights: &[Coord],
+    ) -> String {
+        let ((min_x, min_y), (max_x, max_y)) = coverage_bounds(pairs);
+        let ((min_x, min_y), (max_x, max_y)) = ((min_x - 1, min_y - 1), (max_x + 1, max_y + 1));
+        let size = (max_x - min_x).max(max_y - min_y) as f64;
+        let dot = size / 300.0;
+
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"{min_x} {min_y} {} {}\">\n",
+            max_x - min_x,
+            max_y - min_y
+        );
+        for &((sx, sy), _, r) in pairs {
+            let r = r as f64 + 0.5;
+            let (sx, sy) = (sx as f64, sy as f64);
+            svg += &format!(
+                "  <polygon points=\"{},{sy} {sx},{} {},{sy} {sx},{}\" fill=\"steelblue\" fill-opacity=\"0.3\" stroke=\"steelblue\" stroke-width=\"{}\"/>\n",
+                sx - r,
+                sy - r,
+                sx + r,
+                sy + r,
+                dot / 4.0
+            );
+        }
+        if let Some(((x0, y0), (x1, y1))) = clip {
+            svg += &format!(
+                "  <rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"none\" stroke=\"black\" stroke-width=\"{}\"/>\n",
+                x0 as f64 - 0.5,
+                y0 as f64 - 0.5,
+                x1 - x0 + 1,
+                y1 - y0 + 1,
+                dot / 2.0
+            );
+        }
+        for &((sx, sy), (bx, by), _) in pairs {
+            svg += &format!("  <circle cx=\"{sx}\" cy=\"{sy}\" r=\"{dot}\" fill=\"navy\"/>\n");
+            svg += &format!(
+                "  <rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"darkgreen\"/>\n",
+                bx as f64 - dot,
+                by as f64 - dot,
+                2.0 * dot,
+                2.0 * dot
+            );
+        }
+        for &(x, y) in highlights {
+            svg += &format!(
+                "  <circle cx=\"{x}\" cy=\"{y}\" r=\"{}\" fill=\"red\"/>\n",
+                2.0 * dot
+            );
+        }
+        svg += "</svg>\n";
+        svg
+    }
+
     fn tuning_frequency(x: i32, y: i32) -> BigInt {
         let gap_x: BigInt = x.to_bigint().unwrap();
         let row = y.to_bigint().unwrap();
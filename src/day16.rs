@@ -1,7 +1,7 @@
 #[cfg(test)]
 mod tests {
     use std::{
-        collections::{HashMap, HashSet},
+        collections::{HashMap, VecDeque},
         error::Error,
     };
 
@@ -9,193 +9,309 @@ mod tests {
 
     use crate::common::tests::{get_input, get_sample_input};
 
-    type NodeId = String;
+    const START: &str = "AA";
 
-    struct Graph {
-        nodes: HashSet<String>,
-        connections: HashMap<String, Vec<(String, usize)>>,
-        rates: HashMap<String, usize>,
-        valve_count: usize,
+    struct Valve {
+        name: String,
+        rate: usize,
+        tunnels: Vec<String>,
     }
 
-    impl Graph {
-        fn neighbours(&self, node: &str) -> &Vec<(String, usize)> {
-            let r = self.connections.get(node).unwrap();
-            r
-        }
+    fn parse(input: &str) -> Result<Vec<Valve>, Box<dyn Error>> {
+        //Valve AA has flow rate=0; tunnels lead to valves DD, II, BB
+        let regex = regex::Regex::new(
+            "Valve (?P<name>.*) has flow rate=(?P<rate>\\d+); tunnel(s?) lead(s?) to valve(s?) (?P<connections>.*)"
+        )?;
 
-        fn rate_of(&self, node: &str) -> usize {
-            self.rates.get(node).map_or(0, |r| r.clone())
+        let mut valves = Vec::new();
+        for line in input.lines() {
+            let cap = regex
+                .captures(line)
+                .ok_or_else(|| format!("Unrecognized valve description: {line}"))?;
+            valves.push(Valve {
+                name: cap["name"].to_string(),
+                rate: cap["rate"].parse()?,
+                tunnels: cap["connections"]
+                    .split(", ")
+                    .map(|s| s.to_string())
+                    .collect_vec(),
+            });
         }
+        Ok(valves)
     }
 
-    const MAX_COST: usize = 30;
+    /// The tunnel network reduced to the valves that are worth opening, plus the start valve.
+    /// Valves `0..valve_count()` have a positive flow rate, the start is the last node.
+    struct ValveGraph {
+        names: Vec<String>,
+        rates: Vec<usize>,
+        distances: Vec<Vec<usize>>,
+    }
 
-    #[test]
-    fn day16() -> Result<(), Box<dyn Error>> {
-        //Valve AA has flow rate=0; tunnels lead to valves DD, II, BB
-        let regex = regex::Regex::new(
-            "Valve (?P<name>.*) has flow rate=(?P<rate>\\d+); tunnel(s?) lead(s?) to valve(s?) (?P<connections>.*)"
-        )?;
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    struct Opening {
+        minute: usize,
+        valve: String,
+    }
 
-        let input = get_input(16)?;
+    #[derive(Debug, Clone, Default)]
+    struct Schedule {
+        pressure: usize,
+        openings: Vec<Opening>,
+    }
 
-        let input = get_sample_input(16)?;
+    type Memo = HashMap<(usize, usize, u32), usize>;
 
-        let mut names = HashSet::new();
-        let mut rates = HashMap::new();
-        let mut connections = HashMap::new();
-        let mut valve_count = 0;
+    impl ValveGraph {
+        fn new(valves: &[Valve]) -> Result<ValveGraph, Box<dyn Error>> {
+            let index: HashMap<&str, usize> = valves
+                .iter()
+                .enumerate()
+                .map(|(i, v)| (v.name.as_str(), i))
+                .collect();
+            let start = *index.get(START).ok_or("No start valve")?;
 
-        for line in input.lines() {
-            let cap = regex.captures(line).unwrap();
-            let name = cap.name("name").unwrap().as_str();
-            let rate: usize = cap.name("rate").unwrap().as_str().parse()?;
-            let mut c = cap
-                .name("connections")
-                .unwrap()
-                .as_str()
-                .split(", ")
-                .into_iter()
-                .map(|s| (s.to_string(), 1))
+            let mut nodes = (0..valves.len())
+                .filter(|&i| valves[i].rate > 0)
                 .collect_vec();
+            if nodes.len() >= 32 {
+                Err("Too many valves with a positive flow rate")?
+            }
+            nodes.push(start);
 
-
-            // println!(
-            //     "  {name} [fontsize={} label=\"{name} {rate}\"]",
-            //     1 + 3 * rate
-            // );
-            // // println!("  \"{name}'\" [fontsize={} label=\"{name}' {rate}\"]", 1 + 3*rate );
-            // for c in &c {
-            // // println!("  {name} -> \"{name}'\"");
-            // println!("  {name} -> {}", c);
-            // // println!("  \"{name}'\" -> {}", c);
-            // }
-
-            names.insert(name.to_string());
-
-            if rate > 0 {
-                let opened_name = name.to_string() + "'";
-                rates.insert(opened_name.clone(), rate);
-                names.insert(opened_name.clone());
-                connections.insert(opened_name.clone(), vec![(name.to_string(), 0)]);
-                c.insert(0, (opened_name.clone(), 1));
-                valve_count += 1;
+            let mut distances = Vec::with_capacity(nodes.len());
+            for &from in &nodes {
+                // plain BFS over the raw tunnels, every tunnel takes one minute
+                let mut raw_distances = vec![usize::MAX; valves.len()];
+                raw_distances[from] = 0;
+                let mut frontier = VecDeque::from([from]);
+                while let Some(current) = frontier.pop_front() {
+                    for tunnel in &valves[current].tunnels {
+                        let next = *index
+                            .get(tunnel.as_str())
+                            .ok_or_else(|| format!("Unknown valve {tunnel}"))?;
+                        if raw_distances[next] == usize::MAX {
+                            raw_distances[next] = raw_distances[current] + 1;
+                            frontier.push_back(next);
+                        }
+                    }
+                }
+                distances.push(nodes.iter().map(|&to| raw_distances[to]).collect_vec());
             }
-            connections.insert(name.to_string(), c.clone());
+
+            Ok(ValveGraph {
+                names: nodes.iter().map(|&i| valves[i].name.clone()).collect(),
+                rates: nodes.iter().map(|&i| valves[i].rate).collect(),
+                distances,
+            })
         }
 
-        let graph = Graph {
-            nodes: names,
-            connections,
-            rates,
-            valve_count,
-        };
-
-        let mut path = Vec::with_capacity(60);
-        path.push("AA".to_string());
-        let mut visited_nodes = HashSet::with_capacity(graph.nodes.len() * 2);
-        visited_nodes.insert("AA".to_string());
-        let mut visited_tunnels = HashSet::with_capacity(500);
-
-        let mut best_rate = 0;
-        let mut best_path = Vec::with_capacity(60);
-
-        explore_path(
-            &mut path,
-            0,
-            0,
-            0,
-            &mut visited_nodes,
-            &mut visited_tunnels,
-            &graph,
-            &mut best_rate,
-            &mut best_path,
-        );
+        fn valve_count(&self) -> usize {
+            self.names.len() - 1
+        }
 
-        println!("Best flow: {best_rate}");
-        println!("Best path: {best_path:?}");
+        fn start(&self) -> usize {
+            self.valve_count()
+        }
 
-        Ok(())
-    }
+        fn all_valves(&self) -> u32 {
+            (1u32 << self.valve_count()) - 1
+        }
 
-    fn explore_path(
-        current_path: &mut Vec<String>,
-        current_cost: usize,
-        current_pressure_reduction: usize,
-        open_valves: usize,
-        visited_nodes: &mut HashSet<String>,
-        visited_tunnels: &mut HashSet<(String, String)>,
-        graph: &Graph,
-        best_reduction_so_far: &mut usize,
-        best_path_so_far: &mut Vec<String>,
-    ) {
-        println!("exploring after {current_path:?}");
-        let current = current_path.last().unwrap().as_str();
-        let t = current.to_string();
-
-        if &current_pressure_reduction > best_reduction_so_far {
-            *best_reduction_so_far = current_pressure_reduction;
-            *best_path_so_far = current_path.clone();
-            println!(
-                "new best path: {best_reduction_so_far}, {}:  {best_path_so_far:?}",
-                best_path_so_far.len()
-            );
+        /// The remaining valves that can still be reached and opened in time, with the time left after opening them.
+        fn candidates(
+            &self,
+            position: usize,
+            time_left: usize,
+            remaining: u32,
+        ) -> impl Iterator<Item = (usize, usize)> + '_ {
+            (0..self.valve_count())
+                .filter(move |valve| remaining & (1 << valve) != 0)
+                .filter_map(move |valve| {
+                    let cost = self.distances[position][valve].saturating_add(1);
+                    if cost < time_left {
+                        Some((valve, time_left - cost))
+                    } else {
+                        None
+                    }
+                })
         }
-        if current_cost >= MAX_COST {
-            //time's up,
-            return;
+
+        /// Most pressure that can still be released from `position` by opening valves out of `remaining`.
+        fn best_from(
+            &self,
+            position: usize,
+            time_left: usize,
+            remaining: u32,
+            memo: &mut Memo,
+        ) -> usize {
+            if let Some(&best) = memo.get(&(position, time_left, remaining)) {
+                return best;
+            }
+            let mut best = 0;
+            for (valve, left) in self.candidates(position, time_left, remaining) {
+                let released = self.rates[valve] * left
+                    + self.best_from(valve, left, remaining & !(1 << valve), memo);
+                best = best.max(released);
+            }
+            memo.insert((position, time_left, remaining), best);
+            best
         }
 
-        let neighbours = graph.neighbours(current);
-        let mut visit_count = 0;
-        for neighbour in neighbours {
-            let (neighbour, cost) = neighbour;
+        /// The best schedule for a single agent, only opening valves in `allowed`.
+        fn schedule(&self, minutes: usize, allowed: u32) -> Schedule {
+            let mut memo = Memo::new();
+            let pressure = self.best_from(self.start(), minutes, allowed, &mut memo);
 
-            let cost_to_go_to_neighbor = cost;
-            if open_valves >= graph.valve_count {
-                continue;
+            // walk the memo table again to recover the choices behind the best value
+            let mut openings = Vec::new();
+            let (mut position, mut time_left, mut remaining) = (self.start(), minutes, allowed);
+            let mut still_to_release = pressure;
+            while still_to_release > 0 {
+                let (valve, left) = self
+                    .candidates(position, time_left, remaining)
+                    .find(|&(valve, left)| {
+                        self.rates[valve] * left
+                            + self.best_from(valve, left, remaining & !(1 << valve), &mut memo)
+                            == still_to_release
+                    })
+                    .unwrap();
+                openings.push(Opening {
+                    minute: minutes - left,
+                    valve: self.names[valve].clone(),
+                });
+                still_to_release -= self.rates[valve] * left;
+                (position, time_left, remaining) = (valve, left, remaining & !(1 << valve));
             }
-            if (neighbour.ends_with("'") && visited_nodes.contains(neighbour))
-                || (visited_tunnels.contains(&(t.clone(), neighbour.to_string()))
-                    && visited_tunnels.contains(&(neighbour.to_string(), t.clone())))
-            {
-                continue;
+
+            Schedule { pressure, openings }
+        }
+
+        /// Every path one agent can walk, recording the best pressure per set of opened valves.
+        fn record_paths(
+            &self,
+            position: usize,
+            time_left: usize,
+            opened: u32,
+            released: usize,
+            best: &mut [usize],
+        ) {
+            let slot = &mut best[opened as usize];
+            *slot = (*slot).max(released);
+            for (valve, left) in self.candidates(position, time_left, !opened & self.all_valves()) {
+                self.record_paths(
+                    valve,
+                    left,
+                    opened | (1 << valve),
+                    released + self.rates[valve] * left,
+                    best,
+                );
             }
+        }
+
+        /// The best schedules for two agents working in parallel, e.g. you and an elephant.
+        fn team_schedules(&self, minutes: usize) -> [Schedule; 2] {
+            let mut best = vec![0; 1 << self.valve_count()];
+            self.record_paths(self.start(), minutes, 0, 0, &mut best);
 
-            visit_count += 1;
-
-            // let t = current.to_string();
-            // println!("{t} -> {neighbour}, current cost: {current_cost}, current reduction: {current_pressure_reduction}");
-            // let start = if t.ends_with("'") {(&t[0..t.len()-1]).to_string()} else {t.clone()};
-            visited_tunnels.insert((t.clone(), neighbour.to_string()));
-            visited_nodes.insert(neighbour.to_string());
-            current_path.push(neighbour.to_string());
-            let neighbour_rate = graph.rate_of(neighbour);
-            if current_cost + cost_to_go_to_neighbor > MAX_COST {
-                continue;
+            // opening a superset of valves is never worse than opening one of its subsets
+            for bit in 0..self.valve_count() {
+                for mask in 0..best.len() {
+                    if mask & (1 << bit) != 0 {
+                        best[mask] = best[mask].max(best[mask ^ (1 << bit)]);
+                    }
+                }
             }
-            let reduction = (MAX_COST - current_cost - cost_to_go_to_neighbor) * neighbour_rate;
-            let valve_inc = if neighbour.ends_with("'") { 1 } else { 0 };
-            explore_path(
-                current_path,
-                current_cost + cost_to_go_to_neighbor,
-                current_pressure_reduction + reduction,
-                open_valves + valve_inc,
-                visited_nodes,
-                visited_tunnels,
-                graph,
-                best_reduction_so_far,
-                best_path_so_far,
-            );
-            visited_tunnels.remove(&(t.clone(), neighbour.to_string()));
-            visited_nodes.remove(neighbour);
-            current_path.pop();
+
+            let all = self.all_valves() as usize;
+            let mine = (0..best.len())
+                .max_by_key(|&mask| best[mask] + best[all ^ mask])
+                .unwrap() as u32;
+
+            [
+                self.schedule(minutes, mine),
+                self.schedule(minutes, self.all_valves() ^ mine),
+            ]
         }
+    }
 
-        if visit_count == 0 {
-            // println!("Blocked!");
-            return;
+    fn print_schedule(who: &str, schedule: &Schedule) {
+        for opening in &schedule.openings {
+            println!(
+                "{who} open valve {} in minute {}",
+                opening.valve, opening.minute
+            );
         }
     }
+
+    #[test]
+    fn day16_part1_sample() -> Result<(), Box<dyn Error>> {
+        let input = get_sample_input(16)?;
+        let graph = ValveGraph::new(&parse(&input)?)?;
+
+        let schedule = graph.schedule(30, graph.all_valves());
+        assert_eq!(schedule.pressure, 1651);
+        assert_eq!(
+            schedule
+                .openings
+                .iter()
+                .map(|o| (o.minute, o.valve.as_str()))
+                .collect_vec(),
+            vec![
+                (2, "DD"),
+                (5, "BB"),
+                (9, "JJ"),
+                (17, "HH"),
+                (21, "EE"),
+                (24, "CC")
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn day16_part2_sample() -> Result<(), Box<dyn Error>> {
+        let input = get_sample_input(16)?;
+        let graph = ValveGraph::new(&parse(&input)?)?;
+
+        let [me, elephant] = graph.team_schedules(26);
+        assert_eq!(me.pressure + elephant.pressure, 1707);
+
+        let opened = me
+            .openings
+            .iter()
+            .chain(elephant.openings.iter())
+            .map(|o| o.valve.as_str())
+            .sorted()
+            .collect_vec();
+        assert_eq!(opened, vec!["BB", "CC", "DD", "EE", "HH", "JJ"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn day16_part1() -> Result<(), Box<dyn Error>> {
+        let input = get_input(16)?;
+        let graph = ValveGraph::new(&parse(&input)?)?;
+
+        let schedule = graph.schedule(30, graph.all_valves());
+        print_schedule("You", &schedule);
+        println!("Best flow: {}", schedule.pressure);
+
+        Ok(())
+    }
+
+    #[test]
+    fn day16_part2() -> Result<(), Box<dyn Error>> {
+        let input = get_input(16)?;
+        let graph = ValveGraph::new(&parse(&input)?)?;
+
+        let [me, elephant] = graph.team_schedules(26);
+        print_schedule("You", &me);
+        print_schedule("The elephant", &elephant);
+        println!("Best flow together: {}", me.pressure + elephant.pressure);
+
+        Ok(())
+    }
 }
@@ -1,206 +1,277 @@
 #[cfg(test)]
 mod tests {
     use crate::common::tests::{get_input, get_sample_input};
-    use std::collections::HashSet;
+    use std::collections::{HashMap, VecDeque};
     use std::error::Error;
 
-    #[derive(Copy, Clone, Debug)]
-    enum Block {
-        Line,
-        Cross,
-        MirroredEl,
-        I,
-        Square,
-    }
-
     type Ord = i64;
     type Coord = (Ord, Ord);
 
-    fn points(block: &Block) -> Vec<Coord> {
-        match *block {
-            Block::Line => vec![(0, 0), (1, 0), (2, 0), (3, 0)],
-            Block::Cross => vec![(0, 1), (1, 0), (1, 1), (2, 1), (1, 2)],
-            Block::MirroredEl => vec![(0, 0), (1, 0), (2, 0), (2, 1), (2, 2)],
-            Block::I => vec![(0, 0), (0, 1), (0, 2), (0, 3)],
-            Block::Square => vec![(0, 0), (1, 0), (0, 1), (1, 1)],
-        }
+    /// Distance between a new rock and the left wall.
+    const SPAWN_X: Ord = 2;
+    /// Number of empty rows between a new rock and the top of the tower.
+    const SPAWN_GAP: Ord = 3;
+
+    fn standard_pieces() -> Vec<Vec<Coord>> {
+        vec![
+            vec![(0, 0), (1, 0), (2, 0), (3, 0)],
+            vec![(0, 1), (1, 0), (1, 1), (2, 1), (1, 2)],
+            vec![(0, 0), (1, 0), (2, 0), (2, 1), (2, 2)],
+            vec![(0, 0), (0, 1), (0, 2), (0, 3)],
+            vec![(0, 0), (1, 0), (0, 1), (1, 1)],
+        ]
     }
 
-    fn next_position_free(
-        current: &Coord,
-        direction: &Coord,
-        occupied_positions: &HashSet<Coord>,
-        block: &Block,
-        field_width: Ord,
-    ) -> bool {
-        let position = current;
-        let jet_dir = direction;
-        let (pos_x, pos_y) = position;
-
-        for (relative_x, relative_y) in points(block) {
-            let absolute_x = relative_x + pos_x;
-            let absolute_y = relative_y + pos_y;
-            let future_x = absolute_x + jet_dir.0;
-            let future_y = absolute_y + jet_dir.1;
-            if occupied_positions.contains(&(future_x, future_y))
-                || future_x >= field_width
-                || future_x < 0
-                || future_y < 0
-            {
-                return false;
-            }
-        }
-        return true;
+    fn parse_jets(input: &str) -> Result<Vec<Ord>, Box<dyn Error>> {
+        input
+            .trim()
+            .chars()
+            .map(|jet| match jet {
+                '>' => Ok(1),
+                '<' => Ok(-1),
+                _ => Err(format!("Unsupported input! {jet}").into()),
+            })
+            .collect()
     }
 
-    #[test]
-    fn day17() -> Result<(), Box<dyn Error>> {
-
-        // in sample input: 
-        // there's a pattern of 53 lines (for every 35 blocks) that keeps repeating after a prelude of 36 lines (from the first 20 blocks)
-        // checking the output, the last remaining 30 blocks go 42 lines high
-        
-        // 1_000_000_000_000 - 20 = 999999999980 # minus the number of blocks in the prelude
-        // 999999999980 / 35 = 28571428570       # number of times the pattern repeats
-        // 999999999980 % 35 = 30                # remaining lines
-        // 28571428570 * 53 = 1514285714210
-
-        // total: 36 + (28571428570 * 53) + 42 = 1514285714288 # which matches the example of day 17
-        // let input = get_sample_input(17)?;
-
-
-        // in real input:
-        // there is a prelude of 3460 lines for 2185 blocks
-        // there is a pattern of 2781 lines for every 1735 blocks
-        // there is a final block of 2293 lines for the remaining 1425 blocks
-        // total height after 1_000_000_000_000 blocks = 3460 + (576368874 * 2781) + 2293 = 1602881844347 lines
-
-        // repeating pattern of 784 - 437 + 1 = 348 blocks ; 6240 - 3459 = 2781 lines
-        // repeating pattern of 347 * 5 = 1735 blocks ; 6240 - 3459 = 2781 lines
-        // block count of repeating pattern: 3920 - 2186 + 1 = 1735
-        // 1_000_000_000_000 - 2185 = 999999997815  # minus the number of blocks in the prelude
-        // 999999997815 / 1735 = 576368874          # number of times the pattern repeats
-        // 999999997815 % 1735 = 1425               # remaining lines
-        
-        // all extracted from this output, as generated below
-        //(line : log)
-        //
-        //    1 : Placing line block at 2 (j%len(): 3)  - false - max_height before -1
-        // ...
-        // 2186 : Placing line block at 2 (j%len(): 2712)  - true - max_height before 3459    ----> BEGIN OF REPEATED PATTERN
-        // 2187 : Placing block Cross, max_height before: 3460
-        // ...
-        // 3609: Placing block I, max_height before: 5746
-        // 3610: Placing block Square, max_height before: 5750
-        // 3611: Placing line block at 3 (j%len(): 846)  - true - max_height before 5752
-        // 3612: Placing block Cross, max_height before: 5753
-        // 3613: Placing block MirroredEl, max_height before: 5754
-        // ...
-        // 3920: Placing block Square, max_height before: 6240
-        // 3921: Placing line block at 2 (j%len(): 2712)  - true - max_height before 6240    ----> BEGIN OF REPEATED PATTERN AGAIN
-        // 3922: Placing block Cross, max_height before: 6241
-        
-        let input = get_input(17)?;
+    /// Key identifying the state of a chamber for cycle detection: next piece, next jet and surface profile.
+    type StateKey = (usize, usize, Vec<usize>);
+
+    /// Simulates rocks falling into a chamber, one rock at a time.
+    ///
+    /// Only the rows that a falling rock can still reach are kept: everything below them behaves
+    /// exactly like the floor, so they are dropped and only counted in the height.
+    struct Chamber {
+        pieces: Vec<Vec<Coord>>,
+        width: usize,
+        jets: Vec<Ord>,
+        /// occupied cells per row as a bitmask, the lowest retained row first
+        rows: VecDeque<u64>,
+        dropped_rows: u64,
+        next_piece: usize,
+        next_jet: usize,
+        rocks: u64,
+    }
 
-        let input = input.trim();
-        let num_of_jets = input.len();
+    impl Chamber {
+        fn new(
+            pieces: Vec<Vec<Coord>>,
+            width: usize,
+            jets: Vec<Ord>,
+        ) -> Result<Chamber, Box<dyn Error>> {
+            if width == 0 || width > 64 {
+                Err(format!("Unsupported chamber width {width}"))?
+            }
+            if pieces.is_empty() || jets.is_empty() {
+                Err("A chamber needs at least one piece and one jet")?
+            }
+            for piece in &pieces {
+                if piece.is_empty() || piece.iter().any(|&(x, y)| x < 0 || y < 0) {
+                    Err(format!("Invalid piece {piece:?}"))?
+                }
+                if piece.iter().any(|&(x, _)| SPAWN_X + x >= width as Ord) {
+                    Err(format!(
+                        "Piece {piece:?} doesn't fit in a chamber of width {width}"
+                    ))?
+                }
+            }
 
-        println!("Input length: {}", input.len());
+            Ok(Chamber {
+                pieces,
+                width,
+                jets,
+                rows: VecDeque::new(),
+                dropped_rows: 0,
+                next_piece: 0,
+                next_jet: 0,
+                rocks: 0,
+            })
+        }
 
-        let blocks = vec![
-            Block::Line,
-            Block::Cross,
-            Block::MirroredEl,
-            Block::I,
-            Block::Square,
-        ];
+        fn height(&self) -> u64 {
+            self.dropped_rows + self.rows.len() as u64
+        }
 
-        let num_of_blocks = blocks.len();
+        fn rocks(&self) -> u64 {
+            self.rocks
+        }
 
-        println!("i64::MAX: {}", i64::MAX);
-        // let block_count = 2022;
-        let block_count = 1_000_000_000_000;
-        let mut blocks = blocks.iter().cycle().take(block_count);
-        let mut jets = input.chars().cycle().enumerate();
+        fn retained_rows(&self) -> usize {
+            self.rows.len()
+        }
 
-        let mut occupied_positions: HashSet<Coord> = HashSet::new();
-        let mut highest_rock = -1;
-        const FIELD_WIDTH: usize = 7;
-        let mut skyline = [-1; FIELD_WIDTH];
-        let field_width = FIELD_WIDTH as Ord;
+        /// Is the cell free? Coordinates are relative to the lowest retained row.
+        fn is_free(&self, x: Ord, y: Ord) -> bool {
+            if x < 0 || x >= self.width as Ord || y < 0 {
+                return false;
+            }
+            match self.rows.get(y as usize) {
+                Some(row) => row & (1 << x) == 0,
+                None => true,
+            }
+        }
 
-        let mut interesting = HashSet::new();
+        fn fits(&self, piece: usize, (pos_x, pos_y): Coord) -> bool {
+            self.pieces[piece]
+                .iter()
+                .all(|&(x, y)| self.is_free(pos_x + x, pos_y + y))
+        }
 
-        for (i, block) in blocks.enumerate() {
-            // insert block
-            let mut position = (2, highest_rock + 4);
+        /// Drops the next rock until it comes to rest.
+        fn step(&mut self) {
+            let piece = self.next_piece;
+            let mut position = (SPAWN_X, self.rows.len() as Ord + SPAWN_GAP);
 
             loop {
-                //push block if possible
-                let (j, jet) = jets.next().unwrap();
-
-                let jet_dir = match jet {
-                    '>' => (1, 0),
-                    '<' => (-1, 0),
-                    _ => panic!("Unsupported input! {jet}"),
-                };
-
-                let next_free = next_position_free(
-                    &position,
-                    &jet_dir,
-                    &occupied_positions,
-                    block,
-                    field_width,
-                );
-
-                if next_free {
-                    position = (position.0 + jet_dir.0, position.1 + jet_dir.1);
-                } else {
-                }
+                let jet = self.jets[self.next_jet];
+                self.next_jet = (self.next_jet + 1) % self.jets.len();
 
-                let down_free = next_position_free(
-                    &position,
-                    &(0, -1),
-                    &occupied_positions,
-                    block,
-                    field_width,
-                );
+                if self.fits(piece, (position.0 + jet, position.1)) {
+                    position.0 += jet;
+                }
 
-                if down_free {
-                    position = (position.0, position.1 - 1);
+                if self.fits(piece, (position.0, position.1 - 1)) {
+                    position.1 -= 1;
                 } else {
-                    //place block
+                    break;
+                }
+            }
 
-                    if i % num_of_blocks == 0 {
-                        //first line block, check if we positioned it at that x before, AT THE SAME POSITION IN THE JET INPUT STREAM
-                        let (pos_x, _) = position;
-                        let pos_in_jets = j % num_of_jets;
+            for &(x, y) in &self.pieces[piece] {
+                let (x, y) = ((position.0 + x) as usize, (position.1 + y) as usize);
+                while self.rows.len() <= y {
+                    self.rows.push_back(0);
+                }
+                self.rows[y] |= 1 << x;
+            }
 
-                        let int=  interesting.contains(&(pos_x, pos_in_jets)) ;
-                        interesting.insert((pos_x, pos_in_jets));
+            self.next_piece = (self.next_piece + 1) % self.pieces.len();
+            self.rocks += 1;
+            self.drop_unreachable_rows();
+        }
 
-                        println!("Placing line block at {pos_x} (j%len(): {})  - {int} - max_height before {highest_rock}", j % num_of_jets);
+        /// Flood fills the free cells from above the tower. Rocks only move sideways and down,
+        /// so rows below the lowest reachable cell can never be touched again.
+        fn drop_unreachable_rows(&mut self) {
+            let top = self.rows.len() as Ord;
+            let mut visited = vec![0u64; self.rows.len() + 1];
+            let mut frontier = (0..self.width as Ord).map(|x| (x, top)).collect::<Vec<_>>();
+            let mut lowest = top;
+
+            while let Some((x, y)) = frontier.pop() {
+                if !self.is_free(x, y) || visited[y as usize] & (1 << x) != 0 {
+                    continue;
+                }
+                visited[y as usize] |= 1 << x;
+                lowest = lowest.min(y);
+                frontier.extend([(x - 1, y), (x + 1, y), (x, y - 1)]);
+            }
 
-                    } else {
-                        println!("Placing block {block:?}, max_height before: {highest_rock}");
-                    }
+            self.rows.drain(..lowest as usize);
+            self.dropped_rows += lowest as u64;
+        }
 
-                    for (point_x, point_y) in points(block) {
-                        let (relative_x, relative_y) = position;
-                        let (absolute_x, absolute_y) = (relative_x + point_x, relative_y + point_y);
-                        let x = absolute_x as usize;
-                        skyline[x] = skyline[x].max(absolute_y);
+        /// Per column, how far below the top of the tower the first occupied cell is.
+        fn surface_profile(&self) -> Vec<usize> {
+            (0..self.width)
+                .map(|x| {
+                    self.rows
+                        .iter()
+                        .rev()
+                        .position(|row| row & (1 << x) != 0)
+                        .unwrap_or(self.rows.len())
+                })
+                .collect()
+        }
 
-                        occupied_positions.insert((relative_x + point_x, relative_y + point_y));
-                        highest_rock = highest_rock.max(absolute_y);
-                    }
+        fn state_key(&self) -> StateKey {
+            (self.next_piece, self.next_jet, self.surface_profile())
+        }
 
-                    break;
+        /// The height of the tower once `rocks` rocks have fallen. Once the state of the chamber
+        /// repeats, the remaining whole cycles are skipped instead of simulated.
+        fn height_after(&mut self, rocks: u64) -> u64 {
+            let mut seen: HashMap<StateKey, (u64, u64)> = HashMap::new();
+            let mut target = rocks;
+            let mut skipped_height = 0;
+
+            while self.rocks < target {
+                self.step();
+                if skipped_height > 0 {
+                    continue;
+                }
+                if let Some((rocks_before, height_before)) =
+                    seen.insert(self.state_key(), (self.rocks, self.height()))
+                {
+                    let cycle_rocks = self.rocks - rocks_before;
+                    let cycle_height = self.height() - height_before;
+                    let cycles = (target - self.rocks) / cycle_rocks;
+                    target -= cycles * cycle_rocks;
+                    skipped_height = cycles * cycle_height;
                 }
             }
+
+            self.height() + skipped_height
+        }
+    }
+
+    #[test]
+    fn day17_part1_sample() -> Result<(), Box<dyn Error>> {
+        let input = get_sample_input(17)?;
+        let mut chamber = Chamber::new(standard_pieces(), 7, parse_jets(&input)?)?;
+
+        for _ in 0..2022 {
+            chamber.step();
+        }
+        assert_eq!(chamber.height(), 3068);
+        assert!(chamber.retained_rows() < 100);
+
+        let mut chamber = Chamber::new(standard_pieces(), 7, parse_jets(&input)?)?;
+        assert_eq!(chamber.height_after(2022), 3068);
+
+        Ok(())
+    }
+
+    #[test]
+    fn day17_part2_sample() -> Result<(), Box<dyn Error>> {
+        let input = get_sample_input(17)?;
+        let mut chamber = Chamber::new(standard_pieces(), 7, parse_jets(&input)?)?;
+
+        assert_eq!(chamber.height_after(1_000_000_000_000), 1514285714288);
+
+        Ok(())
+    }
+
+    #[test]
+    fn day17_custom_chamber() -> Result<(), Box<dyn Error>> {
+        // every pair of rocks fills one complete row
+        let mut chamber = Chamber::new(vec![vec![(0, 0), (1, 0)]], 4, parse_jets("<<<<>>>>>")?)?;
+        for _ in 0..10 {
+            chamber.step();
         }
+        assert_eq!(chamber.rocks(), 10);
+        assert_eq!(chamber.height(), 5);
+        assert_eq!(chamber.surface_profile(), vec![0, 0, 0, 0]);
+        assert_eq!(chamber.retained_rows(), 0);
+
+        assert!(Chamber::new(standard_pieces(), 4, parse_jets(">")?).is_err());
+        assert!(parse_jets("<>x").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn day17() -> Result<(), Box<dyn Error>> {
+        let input = get_input(17)?;
+
+        let mut chamber = Chamber::new(standard_pieces(), 7, parse_jets(&input)?)?;
+        println!("height after 2022 rocks: {}", chamber.height_after(2022));
 
-        println!("highest rock: {}", highest_rock + 1);
+        let mut chamber = Chamber::new(standard_pieces(), 7, parse_jets(&input)?)?;
+        println!(
+            "height after 1_000_000_000_000 rocks: {}",
+            chamber.height_after(1_000_000_000_000)
+        );
 
         Ok(())
     }
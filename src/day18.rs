@@ -1,8 +1,9 @@
 #[cfg(test)]
 mod tests {
     use std::{
-        collections::{HashSet, VecDeque},
+        collections::{HashMap, HashSet, VecDeque},
         error::Error,
+        fmt::Write,
     };
 
     use itertools::Itertools;
@@ -12,185 +13,312 @@ mod tests {
     type Ord = i32;
     type Coord = (Ord, Ord, Ord);
 
-    #[test]
-    fn day18() -> Result<(), Box<dyn Error>> {
-        // let input = get_sample_input(18)?;
-        let input = get_input(18)?;
-   
-        let mut min_x = i32::MAX;
-        let mut max_x = i32::MIN;
-        let mut min_y = i32::MAX;
-        let mut max_y = i32::MIN;
-        let mut min_z = i32::MAX;
-        let mut max_z = i32::MIN;
-
-        let mut occupied_cells = HashSet::new();
-        for line in input.lines() {
-            let (x, y, z) = line.split(",").tuples().next().unwrap();
-
-            let (x, y, z) = (x.parse::<Ord>()?, y.parse::<Ord>()?, z.parse::<Ord>()?);
-
-            min_x = min_x.min(x);
-            max_x = max_x.max(x);
-            min_y = min_y.min(y);
-            max_y = max_y.max(y);
-            min_z = min_z.min(z);
-            max_z = max_z.max(z);
-
-            occupied_cells.insert((x, y, z));
-        }
-
-        println!("number of cells {}", occupied_cells.len());
-
-        let mut empty_cells = HashSet::new();
-        for x in min_x..=max_x {
-            for y in min_y..=max_y {
-                for z in min_z..=max_z {
-                    let c = (x, y, z);
-                    if !occupied_cells.contains(&c) {
-                        empty_cells.insert(c);
-                    }
-                }
+    const DIRECTIONS: [Coord; 6] = [
+        (1, 0, 0),
+        (-1, 0, 0),
+        (0, 1, 0),
+        (0, -1, 0),
+        (0, 0, 1),
+        (0, 0, -1),
+    ];
+
+    fn add(a: Coord, b: Coord) -> Coord {
+        (a.0 + b.0, a.1 + b.1, a.2 + b.2)
+    }
+
+    /// The side of a cube that points in one of the six `DIRECTIONS`.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    struct Face {
+        cube: Coord,
+        direction: Coord,
+    }
+
+    impl Face {
+        /// The corners of the face, counter-clockwise when looking at it from outside the cube.
+        fn corners(&self) -> [Coord; 4] {
+            let (x, y, z) = self.cube;
+            let corner = |a: Ord, b: Ord| match self.direction {
+                (1, 0, 0) => (x + 1, y + a, z + b),
+                (-1, 0, 0) => (x, y + b, z + a),
+                (0, 1, 0) => (x + b, y + 1, z + a),
+                (0, -1, 0) => (x + a, y, z + b),
+                (0, 0, 1) => (x + a, y + b, z + 1),
+                _ => (x + b, y + a, z),
+            };
+            [corner(0, 0), corner(1, 0), corner(1, 1), corner(0, 1)]
+        }
+    }
+
+    /// A sparse set of unit cubes.
+    struct Droplet {
+        cubes: HashSet<Coord>,
+        min: Coord,
+        max: Coord,
+    }
+
+    impl Droplet {
+        fn parse(input: &str) -> Result<Droplet, Box<dyn Error>> {
+            let mut cubes = HashSet::new();
+            for line in input.lines() {
+                let (x, y, z) = line
+                    .split(',')
+                    .map(|c| c.trim().parse::<Ord>())
+                    .collect_tuple()
+                    .ok_or_else(|| format!("Not a coordinate: {line}"))?;
+                cubes.insert((x?, y?, z?));
             }
+            Ok(Droplet::new(cubes))
         }
 
-        // redeclars as non-mutable
-        let empty_cells = empty_cells;
-        // println!("empty cells: {empty_cells:?}");
-
-        let directions = vec![
-            (1, 0, 0),
-            (-1, 0, 0),
-            (0, 1, 0),
-            (0, -1, 0),
-            (0, 0, 1),
-            (0, 0, -1),
-        ];
-
-        let total_surface = {
-            // let first = occupied_cells.iter().next().unwrap();
-            let mut unvisited_cells = HashSet::with_capacity(occupied_cells.len());
-            for occupied_cell in occupied_cells.iter() {
-                unvisited_cells.insert(occupied_cell);
+        fn new(cubes: HashSet<Coord>) -> Droplet {
+            let mut min = (Ord::MAX, Ord::MAX, Ord::MAX);
+            let mut max = (Ord::MIN, Ord::MIN, Ord::MIN);
+            for &(x, y, z) in &cubes {
+                min = (min.0.min(x), min.1.min(y), min.2.min(z));
+                max = (max.0.max(x), max.1.max(y), max.2.max(z));
             }
-            let mut visited_cells = HashSet::new();
-            let mut frontier = VecDeque::new();
+            Droplet { cubes, min, max }
+        }
 
-            let mut surface = 0;
+        fn in_bounds(&self, (x, y, z): Coord, padding: Ord) -> bool {
+            (self.min.0 - padding..=self.max.0 + padding).contains(&x)
+                && (self.min.1 - padding..=self.max.1 + padding).contains(&y)
+                && (self.min.2 - padding..=self.max.2 + padding).contains(&z)
+        }
 
-            loop {
-                let mut current = frontier.pop_front();
-                if current.is_none() {
-                    if unvisited_cells.is_empty() {
-                        break;
+        /// All empty cells connected to `start` within the bounding box grown by `padding`.
+        fn flood(&self, start: Coord, padding: Ord) -> HashSet<Coord> {
+            let mut reached = HashSet::from([start]);
+            let mut frontier = VecDeque::from([start]);
+            while let Some(current) = frontier.pop_front() {
+                for direction in DIRECTIONS {
+                    let neighbour = add(current, direction);
+                    if self.in_bounds(neighbour, padding)
+                        && !self.cubes.contains(&neighbour)
+                        && reached.insert(neighbour)
+                    {
+                        frontier.push_back(neighbour);
                     }
-                    // println!("starting a new section");
-                    let some = unvisited_cells.iter().next().unwrap();
-                    current = Some(unvisited_cells.take(*some).unwrap());
-                } else {
-                    // println!("continuing section");
                 }
-                let current = current.unwrap();
-                if !visited_cells.insert(current) {
-                    continue;
-                }
-                unvisited_cells.remove(current);
-                // println!(
-                // "Processing {current:?}, visited cells: {}",
-                // visited_cells.len()
-                // );
-
-                for dir in directions.iter() {
-                    let neighbour = (current.0 + dir.0, current.1 + dir.1, current.2 + dir.2);
-                    if visited_cells.contains(&neighbour) {
-                        continue;
-                    }
+            }
+            reached
+        }
 
-                    match occupied_cells.get(&neighbour) {
-                        Some(cell) => {
-                            frontier.push_back(cell);
-                        }
-                        None => {
-                            surface += 1;
+        /// The empty cells that can be reached from outside, flooding a box one cell larger than
+        /// the droplet, so the flood can get around the droplet on every side.
+        fn exterior(&self) -> HashSet<Coord> {
+            let corner = add(self.min, (-1, -1, -1));
+            self.flood(corner, 1)
+        }
+
+        /// Every face of a cube that isn't covered by another cube.
+        fn faces(&self) -> Vec<Face> {
+            self.cubes
+                .iter()
+                .sorted()
+                .flat_map(|&cube| {
+                    DIRECTIONS
+                        .iter()
+                        .map(move |&direction| Face { cube, direction })
+                })
+                .filter(|face| !self.cubes.contains(&add(face.cube, face.direction)))
+                .collect()
+        }
+
+        /// The faces that are in contact with the outside.
+        fn exterior_faces(&self) -> Vec<Face> {
+            let exterior = self.exterior();
+            self.faces()
+                .into_iter()
+                .filter(|face| exterior.contains(&add(face.cube, face.direction)))
+                .collect()
+        }
+
+        fn surface_area(&self) -> usize {
+            self.faces().len()
+        }
+
+        fn exterior_surface_area(&self) -> usize {
+            self.exterior_faces().len()
+        }
+
+        /// The air pockets trapped inside the droplet, one set of cells per pocket.
+        fn cavities(&self) -> Vec<HashSet<Coord>> {
+            if self.cubes.is_empty() {
+                return Vec::new();
+            }
+            let exterior = self.exterior();
+            let mut seen = HashSet::new();
+            let mut cavities = Vec::new();
+            for x in self.min.0..=self.max.0 {
+                for y in self.min.1..=self.max.1 {
+                    for z in self.min.2..=self.max.2 {
+                        let cell = (x, y, z);
+                        if self.cubes.contains(&cell)
+                            || exterior.contains(&cell)
+                            || seen.contains(&cell)
+                        {
+                            continue;
                         }
+                        let cavity = self.flood(cell, 0);
+                        seen.extend(cavity.iter().copied());
+                        cavities.push(cavity);
                     }
                 }
             }
+            cavities
+        }
+    }
 
-            println!("total surface area: {surface}");
-            surface
-        };
+    /// Wavefront OBJ, sharing the vertices between faces.
+    fn to_obj(faces: &[Face]) -> String {
+        let mut vertices: HashMap<Coord, usize> = HashMap::new();
+        let mut vertex_lines = String::new();
+        let mut face_lines = String::new();
+        for face in faces {
+            let indices = face.corners().map(|corner| {
+                let next = vertices.len() + 1;
+                *vertices.entry(corner).or_insert_with(|| {
+                    writeln!(vertex_lines, "v {} {} {}", corner.0, corner.1, corner.2).unwrap();
+                    next
+                })
+            });
+            writeln!(face_lines, "f {}", indices.iter().join(" ")).unwrap();
+        }
+        vertex_lines + &face_lines
+    }
 
-        let inner_surface = {
-            let mut unvisited_cells = HashSet::with_capacity(empty_cells.len());
-            for empty_cell in empty_cells.iter() {
-                unvisited_cells.insert(empty_cell);
+    /// ASCII STL, two triangles per face.
+    fn to_stl(name: &str, faces: &[Face]) -> String {
+        let mut stl = format!("solid {name}\n");
+        for face in faces {
+            let [a, b, c, d] = face.corners();
+            let (nx, ny, nz) = face.direction;
+            for triangle in [[a, b, c], [a, c, d]] {
+                writeln!(stl, "  facet normal {nx} {ny} {nz}").unwrap();
+                writeln!(stl, "    outer loop").unwrap();
+                for (x, y, z) in triangle {
+                    writeln!(stl, "      vertex {x} {y} {z}").unwrap();
+                }
+                writeln!(stl, "    endloop").unwrap();
+                writeln!(stl, "  endfacet").unwrap();
             }
-            let mut visited_cells = HashSet::new();
-            let mut frontier = VecDeque::new();
-
-            let mut total_inner_surface = 0;
-            let mut inner_surface = 0;
-            let mut is_inner_region = true;
-
-            loop {
-                let mut current = frontier.pop_front();
-                if current.is_none() {
-                    if is_inner_region {
-                        total_inner_surface += inner_surface;
-                    }
+        }
+        writeln!(stl, "endsolid {name}").unwrap();
+        stl
+    }
 
-                    if unvisited_cells.is_empty() {
-                        break;
-                    }
-                    // println!("starting a new region");
-                    is_inner_region = true;
-                    inner_surface = 0;
-                    let some = unvisited_cells.iter().next().unwrap();
-                    current = Some(unvisited_cells.take(*some).unwrap());
-                } else {
-                    // println!("continuing section");
-                }
-                let current = current.unwrap();
-                // println!("current: {current:?}");
-
-                //are we at the edge ?
-                let (x, y, z) = *current;
-                if x <= min_x || x >= max_x || y <= min_y || y >= max_y || z <= min_z || z >= max_z
-                {
-                    // println!("not an inner region");
-                    is_inner_region = false;
-                }
+    #[test]
+    fn day18_sample() -> Result<(), Box<dyn Error>> {
+        let input = get_sample_input(18)?;
+        let droplet = Droplet::parse(&input)?;
+
+        assert_eq!(droplet.surface_area(), 64);
+        assert_eq!(droplet.exterior_surface_area(), 58);
 
-                if !visited_cells.insert(current) {
-                    continue;
+        let cavities = droplet.cavities();
+        assert_eq!(cavities.len(), 1);
+        assert_eq!(cavities[0], HashSet::from([(2, 2, 5)]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn day18_hollow_cube() -> Result<(), Box<dyn Error>> {
+        // a 3x3x3 cube with only its center cell missing
+        let mut cubes = HashSet::new();
+        for x in 0..3 {
+            for y in 0..3 {
+                for z in 0..3 {
+                    cubes.insert((x, y, z));
                 }
-                unvisited_cells.remove(current);
+            }
+        }
+        cubes.remove(&(1, 1, 1));
+        let droplet = Droplet::new(cubes);
 
-                for dir in directions.iter() {
-                    let neighbour = (current.0 + dir.0, current.1 + dir.1, current.2 + dir.2);
-                    if visited_cells.contains(&neighbour) {
-                        continue;
-                    }
+        assert_eq!(droplet.surface_area(), 60);
+        assert_eq!(droplet.exterior_surface_area(), 54);
+        assert_eq!(droplet.cavities(), vec![HashSet::from([(1, 1, 1)])]);
 
-                    match empty_cells.get(&neighbour) {
-                        Some(cell) => {
-                            frontier.push_back(cell);
-                        }
-                        None => {
-                            inner_surface += 1;
-                        }
-                    }
+        let faces = droplet.exterior_faces();
+        for face in &faces {
+            // the corners wind counter-clockwise around the outward normal
+            let [a, b, c, _] = face.corners();
+            let (u, v) = (
+                (b.0 - a.0, b.1 - a.1, b.2 - a.2),
+                (c.0 - a.0, c.1 - a.1, c.2 - a.2),
+            );
+            let normal = (
+                u.1 * v.2 - u.2 * v.1,
+                u.2 * v.0 - u.0 * v.2,
+                u.0 * v.1 - u.1 * v.0,
+            );
+            assert_eq!(normal, face.direction);
+        }
+
+        let obj = to_obj(&faces);
+        assert_eq!(obj.lines().filter(|l| l.starts_with("f ")).count(), 54);
+        // every corner of the 3x3x3 grid on the outside: 4^3 - 2^3
+        assert_eq!(obj.lines().filter(|l| l.starts_with("v ")).count(), 56);
+
+        let stl = to_stl("cube", &faces);
+        assert_eq!(
+            stl.lines()
+                .filter(|l| l.trim().starts_with("facet"))
+                .count(),
+            108
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn day18_thin_droplet_cavity() -> Result<(), Box<dyn Error>> {
+        // a pocket of two cells right behind the outer layer of a droplet that is only three cells thick
+        let mut cubes = HashSet::new();
+        for x in 0..3 {
+            for y in 0..5 {
+                for z in 0..3 {
+                    cubes.insert((x, y, z));
                 }
             }
+        }
+        cubes.remove(&(1, 1, 1));
+        cubes.remove(&(1, 2, 1));
+        let droplet = Droplet::new(cubes);
 
-            // println!("total surface area: {total_inner_surface}");
-            total_inner_surface
-        };
+        assert_eq!(
+            droplet.cavities(),
+            vec![HashSet::from([(1, 1, 1), (1, 2, 1)])]
+        );
+        assert_eq!(droplet.exterior_surface_area(), 2 * (3 * 5 + 3 * 5 + 3 * 3));
+
+        Ok(())
+    }
+
+    #[test]
+    fn day18() -> Result<(), Box<dyn Error>> {
+        let input = get_input(18)?;
+        let droplet = Droplet::parse(&input)?;
+
+        println!("number of cells {}", droplet.cubes.len());
+        println!("total surface area: {}", droplet.surface_area());
+        println!("exterior surface area: {}", droplet.exterior_surface_area());
+        for cavity in droplet.cavities() {
+            println!(
+                "cavity of {} cells: {:?}",
+                cavity.len(),
+                cavity.iter().sorted().next()
+            );
+        }
 
-        println!("total surface area: {total_surface}");
-        println!("of which inner surface: {inner_surface}");
-        println!("relevant surface area: {}", total_surface - inner_surface);
+        let faces = droplet.exterior_faces();
+        let obj_path = std::env::temp_dir().join("day18.obj");
+        std::fs::write(&obj_path, to_obj(&faces))?;
+        let stl_path = std::env::temp_dir().join("day18.stl");
+        std::fs::write(&stl_path, to_stl("droplet", &faces))?;
+        println!("exported the exterior surface to {obj_path:?} and {stl_path:?}");
 
         Ok(())
     }
@@ -1,172 +1,291 @@
 #[cfg(test)]
 mod tests {
-    use std::{
-        collections::{HashSet, VecDeque},
-        error::Error,
-    };
+    use std::error::Error;
 
     use regex::Regex;
 
     use crate::common::tests::{get_input, get_sample_input};
 
-    #[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
-    struct State {
-        minutes_left: u64,
-        ore: u64,
-        clay: u64,
-        obsidian: u64,
-        geodes: u64,
+    const ORE: usize = 0;
+    const CLAY: usize = 1;
+    const OBSIDIAN: usize = 2;
+    const GEODE: usize = 3;
 
-        ore_rate: u64,
-        clay_rate: u64,
-        obsidian_rate: u64,
-        geode_rate: u64,
-    }
+    const RESOURCE_NAMES: [&str; 4] = ["ore", "clay", "obsidian", "geode"];
 
-    #[test]
-    fn day19() -> Result<(), Box<dyn Error>> {
-        // let input = get_sample_input(19)?;
-        let input = get_input(19)?;
+    type Resources = [u32; 4];
 
-        // robots collect resources at 1 per minute
-        // factory takes 1 minute to create robot
-        // start: 1 ore-collecting robot
-        // max number of geodes opened after 24 minutes
+    struct Blueprint {
+        id: u32,
+        /// the cost of a robot collecting the resource at that index
+        costs: [Resources; 4],
+        /// beyond these rates, more robots can't be spent in a single minute
+        max_useful_rates: Resources,
+    }
 
-        let regex = Regex::new("Blueprint \\d+: Each ore robot costs (?P<ore_ore>\\d+) ore. Each clay robot costs (?P<clay_ore>\\d+) ore. Each obsidian robot costs (?P<obsidian_ore>\\d+) ore and (?P<obsidian_clay>\\d+) clay. Each geode robot costs (?P<geode_ore>\\d+) ore and (?P<geode_obsidian>\\d+) obsidian.")?;
+    fn parse(input: &str) -> Result<Vec<Blueprint>, Box<dyn Error>> {
+        let regex = Regex::new("Blueprint (?P<id>\\d+): Each ore robot costs (?P<ore_ore>\\d+) ore. Each clay robot costs (?P<clay_ore>\\d+) ore. Each obsidian robot costs (?P<obsidian_ore>\\d+) ore and (?P<obsidian_clay>\\d+) clay. Each geode robot costs (?P<geode_ore>\\d+) ore and (?P<geode_obsidian>\\d+) obsidian.")?;
 
-        let mut quality_sum = 0;
+        let mut blueprints = Vec::new();
+        for captures in regex.captures_iter(input) {
+            let value = |name: &str| captures[name].parse::<u32>();
 
-        for (i, blueprint) in input.lines().enumerate().take(3) {
-            let index = (i + 1) as u64;
-            let captures = regex.captures(blueprint).unwrap();
+            let mut costs = [[0; 4]; 4];
+            costs[ORE][ORE] = value("ore_ore")?;
+            costs[CLAY][ORE] = value("clay_ore")?;
+            costs[OBSIDIAN][ORE] = value("obsidian_ore")?;
+            costs[OBSIDIAN][CLAY] = value("obsidian_clay")?;
+            costs[GEODE][ORE] = value("geode_ore")?;
+            costs[GEODE][OBSIDIAN] = value("geode_obsidian")?;
+            let mut max_useful_rates = [0, 0, 0, u32::MAX];
+            for cost in &costs {
+                for resource in ORE..GEODE {
+                    max_useful_rates[resource] = max_useful_rates[resource].max(cost[resource]);
+                }
+            }
 
-            let ore_ore: u64 = captures.name("ore_ore").unwrap().as_str().parse()?;
-            let clay_ore: u64 = captures.name("clay_ore").unwrap().as_str().parse()?;
-            let obsidian_ore: u64 = captures.name("obsidian_ore").unwrap().as_str().parse()?;
-            let obsidian_clay: u64 = captures.name("obsidian_clay").unwrap().as_str().parse()?;
-            let geode_ore: u64 = captures.name("geode_ore").unwrap().as_str().parse()?;
-            let geode_obsidian: u64 = captures.name("geode_obsidian").unwrap().as_str().parse()?;
+            blueprints.push(Blueprint {
+                id: value("id")?,
+                costs,
+                max_useful_rates,
+            });
+        }
+        Ok(blueprints)
+    }
 
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    struct Build {
+        /// the minute during which the factory builds the robot
+        minute: u32,
+        robot: usize,
+    }
+
+    #[derive(Clone, Debug, Default)]
+    struct Plan {
+        geodes: u32,
+        builds: Vec<Build>,
+    }
 
-            let max_ore_rate_needed = ore_ore.max(clay_ore).max(obsidian_ore).max(geode_ore);
-            let max_clay_rate_needed = obsidian_clay;
-            let max_obsidian_rate_needed = geode_obsidian;
+    #[derive(Clone, Copy, Debug)]
+    struct State {
+        minutes_left: u32,
+        resources: Resources,
+        robots: Resources,
+    }
 
-            // the production of robots competes on ore for each robot, and nothing else.
+    struct Solver<'a> {
+        blueprint: &'a Blueprint,
+        minutes: u32,
+        best: Plan,
+        builds: Vec<Build>,
+    }
 
-            println!("{blueprint}:\n{ore_ore}, {clay_ore}, {obsidian_ore}, {obsidian_clay}, {geode_ore}, {geode_obsidian}");
+    impl<'a> Solver<'a> {
+        /// Geodes opened at the end if no more robots get built.
+        fn geodes_when_idle(state: &State) -> u32 {
+            state.resources[GEODE] + state.robots[GEODE] * state.minutes_left
+        }
 
-            let state = State {
-                minutes_left: 32,
-                ore: 0,
-                clay: 0,
-                obsidian: 0,
-                geodes: 0,
+        /// An optimistic bound: pretend a new geode robot gets built every remaining minute.
+        fn upper_bound(state: &State) -> u32 {
+            let t = state.minutes_left;
+            Self::geodes_when_idle(state) + t * t.saturating_sub(1) / 2
+        }
 
-                ore_rate: 1,
-                clay_rate: 0,
-                obsidian_rate: 0,
-                geode_rate: 0,
-            };
+        /// Minutes until the robot can be built, including the minute it takes to build it.
+        fn minutes_to_build(&self, state: &State, robot: usize) -> Option<u32> {
+            let mut wait = 0;
+            for resource in ORE..GEODE {
+                let cost = self.blueprint.costs[robot][resource];
+                if cost > state.resources[resource] {
+                    let rate = state.robots[resource];
+                    if rate == 0 {
+                        return None;
+                    }
+                    wait = wait.max((cost - state.resources[resource]).div_ceil(rate));
+                }
+            }
+            Some(wait + 1)
+        }
 
-            let mut frontier = VecDeque::new();
-            frontier.push_back(state);
-            let mut visited_states = HashSet::new();
+        /// Branches on which robot to build next, waiting as long as needed to afford it.
+        fn search(&mut self, state: State) {
+            let idle = Self::geodes_when_idle(&state);
+            if idle > self.best.geodes {
+                self.best = Plan {
+                    geodes: idle,
+                    builds: self.builds.clone(),
+                };
+            }
+            if Self::upper_bound(&state) <= self.best.geodes {
+                return;
+            }
 
-            let mut max_geodes = 0;
-            while !frontier.is_empty() {
-                let current = frontier.pop_front().unwrap();
-                if visited_states.contains(&current) {
-                    // println!("pruned state that we already visited");
+            for robot in (ORE..=GEODE).rev() {
+                if state.robots[robot] >= self.blueprint.max_useful_rates[robot] {
                     continue;
-                } else {
-                    // println!("new state");
-                    visited_states.insert(current);
                 }
+                let minutes = match self.minutes_to_build(&state, robot) {
+                    // a robot finished in the last minute can't collect anything anymore
+                    Some(minutes) if minutes < state.minutes_left => minutes,
+                    _ => continue,
+                };
 
-                if current.minutes_left == 0 {
-                    let score = current.geodes;
-                    // println!("Bottom! Current: {current:?}");
-                    max_geodes = max_geodes.max(score);
-                } else {
-                    let minutes_left = current.minutes_left - 1;
-                    // the state where we don't build any robots
-                    frontier.push_back(State {
-                        minutes_left,
-                        ore: current.ore + current.ore_rate,
-                        clay: current.clay + current.clay_rate,
-                        obsidian: current.obsidian + current.obsidian_rate,
-                        geodes: current.geodes + current.geode_rate,
-
-                        ..current
-                    });
-
-                    // are there any machines we can and should build?
-                    if current.ore >= geode_ore && current.obsidian >= geode_obsidian {
-                        frontier.push_back(State {
-                            minutes_left,
-                            ore: current.ore + current.ore_rate - geode_ore,
-                            clay: current.clay + current.clay_rate,
-                            obsidian: current.obsidian + current.obsidian_rate - geode_obsidian,
-                            geodes: current.geodes + current.geode_rate,
-
-                            geode_rate: current.geode_rate + 1,
-                            ..current
-                        });
-                        continue; // if you can build an obsidian bot, assume that this is the best option
-                    }
-
-                    if current.obsidian_rate < max_obsidian_rate_needed && current.ore >= obsidian_ore && current.clay >= obsidian_clay {
-                        frontier.push_back(State {
-                            minutes_left,
-                            ore: current.ore + current.ore_rate - obsidian_ore,
-                            clay: current.clay + current.clay_rate - obsidian_clay,
-                            obsidian: current.obsidian + current.obsidian_rate,
-                            geodes: current.geodes + current.geode_rate,
+                let mut next = State {
+                    minutes_left: state.minutes_left - minutes,
+                    ..state
+                };
+                for resource in ORE..=GEODE {
+                    next.resources[resource] += state.robots[resource] * minutes;
+                    next.resources[resource] -= self.blueprint.costs[robot][resource];
+                }
+                next.robots[robot] += 1;
 
-                            obsidian_rate: current.obsidian_rate + 1,
-                            ..current
-                        });
+                self.builds.push(Build {
+                    minute: self.minutes - next.minutes_left,
+                    robot,
+                });
+                self.search(next);
+                self.builds.pop();
+            }
+        }
+    }
 
-                        continue; // same here?
-                    }
+    fn best_plan(blueprint: &Blueprint, minutes: u32) -> Plan {
+        let mut solver = Solver {
+            blueprint,
+            minutes,
+            best: Plan::default(),
+            builds: Vec::new(),
+        };
+        solver.search(State {
+            minutes_left: minutes,
+            resources: [0; 4],
+            robots: [1, 0, 0, 0],
+        });
+        solver.best
+    }
 
-                    if current.ore_rate < max_ore_rate_needed && current.ore >= ore_ore {
-                        frontier.push_back(State {
-                            minutes_left,
-                            ore: current.ore + current.ore_rate - ore_ore,
-                            clay: current.clay + current.clay_rate,
-                            obsidian: current.obsidian + current.obsidian_rate,
-                            geodes: current.geodes + current.geode_rate,
-
-                            ore_rate: current.ore_rate + 1,
-                            ..current
-                        });
-                    }
+    /// Runs the factory minute by minute following the build order, returning the geodes opened.
+    fn replay(
+        blueprint: &Blueprint,
+        minutes: u32,
+        builds: &[Build],
+    ) -> Result<u32, Box<dyn Error>> {
+        let mut resources: Resources = [0; 4];
+        let mut robots = [1, 0, 0, 0];
+        let mut builds = builds.iter().peekable();
 
-                    if current.clay_rate < max_clay_rate_needed && current.ore >= clay_ore {
-                        frontier.push_back(State {
-                            minutes_left,
-                            ore: current.ore + current.ore_rate - clay_ore,
-                            clay: current.clay + current.clay_rate,
-                            obsidian: current.obsidian + current.obsidian_rate,
-                            geodes: current.geodes + current.geode_rate,
-
-                            clay_rate: current.clay_rate + 1,
-                            ..current
-                        });
-                    }
+        for minute in 1..=minutes {
+            let building = builds
+                .next_if(|build| build.minute == minute)
+                .map(|build| build.robot);
+            if let Some(robot) = building {
+                for (amount, cost) in resources.iter_mut().zip(blueprint.costs[robot]) {
+                    *amount = amount.checked_sub(cost).ok_or_else(|| {
+                        format!(
+                            "Can't afford a {} robot in minute {minute}",
+                            RESOURCE_NAMES[robot]
+                        )
+                    })?;
                 }
             }
+            for (amount, rate) in resources.iter_mut().zip(robots) {
+                *amount += rate;
+            }
+            if let Some(robot) = building {
+                robots[robot] += 1;
+            }
+        }
+
+        if let Some(build) = builds.next() {
+            Err(format!(
+                "Build in minute {} is out of order or too late",
+                build.minute
+            ))?
+        }
+        Ok(resources[GEODE])
+    }
+
+    fn quality_level_sum(blueprints: &[Blueprint], minutes: u32) -> u32 {
+        blueprints
+            .iter()
+            .map(|blueprint| blueprint.id * best_plan(blueprint, minutes).geodes)
+            .sum()
+    }
+
+    fn first_three_product(blueprints: &[Blueprint], minutes: u32) -> u32 {
+        blueprints
+            .iter()
+            .take(3)
+            .map(|blueprint| best_plan(blueprint, minutes).geodes)
+            .product()
+    }
 
+    fn print_plan(blueprint: &Blueprint, plan: &Plan) {
+        println!("Blueprint {}: {} geodes", blueprint.id, plan.geodes);
+        for build in &plan.builds {
             println!(
-                "max geodes for blueprint {index}: {max_geodes} (quality level: {})",
-                index * max_geodes
+                "  minute {:2}: build a {} robot",
+                build.minute, RESOURCE_NAMES[build.robot]
             );
-            quality_sum += index * max_geodes;
         }
-        println!("sum of quality levels: {quality_sum}");
+    }
+
+    #[test]
+    fn day19_part1_sample() -> Result<(), Box<dyn Error>> {
+        let input = get_sample_input(19)?;
+        let blueprints = parse(&input)?;
+
+        let plan = best_plan(&blueprints[0], 24);
+        assert_eq!(plan.geodes, 9);
+        assert_eq!(replay(&blueprints[0], 24, &plan.builds)?, 9);
+        assert_eq!(quality_level_sum(&blueprints, 24), 33);
+
+        Ok(())
+    }
+
+    #[test]
+    fn day19_part2_sample() -> Result<(), Box<dyn Error>> {
+        let input = get_sample_input(19)?;
+        let blueprints = parse(&input)?;
+
+        let plan = best_plan(&blueprints[0], 32);
+        assert_eq!(plan.geodes, 56);
+        assert_eq!(replay(&blueprints[0], 32, &plan.builds)?, 56);
+        assert_eq!(best_plan(&blueprints[1], 32).geodes, 62);
+        assert_eq!(first_three_product(&blueprints, 32), 56 * 62);
+
+        Ok(())
+    }
+
+    #[test]
+    fn day19_part1() -> Result<(), Box<dyn Error>> {
+        let input = get_input(19)?;
+        let blueprints = parse(&input)?;
+
+        for blueprint in &blueprints {
+            print_plan(blueprint, &best_plan(blueprint, 24));
+        }
+        println!(
+            "sum of quality levels: {}",
+            quality_level_sum(&blueprints, 24)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn day19_part2() -> Result<(), Box<dyn Error>> {
+        let input = get_input(19)?;
+        let blueprints = parse(&input)?;
+
+        for blueprint in blueprints.iter().take(3) {
+            print_plan(blueprint, &best_plan(blueprint, 32));
+        }
+        println!(
+            "product of max geodes of the first three blueprints: {}",
+            first_three_product(&blueprints, 32)
+        );
         Ok(())
     }
 }
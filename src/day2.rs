@@ -2,137 +2,421 @@
 mod tests {
 
     use crate::common::tests::get_input;
+    use std::{error::Error, fmt};
 
-    #[derive(Clone, Copy, Debug)]
-    enum Choice {
-        ROCK,
-        PAPER,
-        SCISSORS,
-    }
-
-    impl Choice {
-        fn score(&self) -> u32 {
-            match &self {
-                Self::ROCK => 1,
-                Self::PAPER => 2,
-                Self::SCISSORS => 3,
+    use itertools::Itertools;
+
+    /// A shape, as its index in the cycle of its game.
+    type Shape = usize;
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    enum Outcome {
+        Loss,
+        Draw,
+        Win,
+    }
+
+    const OUTCOMES: [Outcome; 3] = [Outcome::Loss, Outcome::Draw, Outcome::Win];
+
+    impl fmt::Display for Outcome {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                Outcome::Loss => write!(f, "lose"),
+                Outcome::Draw => write!(f, "draw"),
+                Outcome::Win => write!(f, "win"),
             }
         }
+    }
 
-        fn compare(&self, other: &Choice) -> Outcome {
-            match (&self, other) {
-                (&Self::ROCK, &Self::ROCK) => Outcome::DRAW,
-                (&Self::ROCK, &Self::SCISSORS) => Outcome::WIN,
-                (&Self::ROCK, &Self::PAPER) => Outcome::LOSS,
-
-                (&Self::PAPER, &Self::PAPER) => Outcome::DRAW,
-                (&Self::PAPER, &Self::ROCK) => Outcome::WIN,
-                (&Self::PAPER, &Self::SCISSORS) => Outcome::LOSS,
+    #[derive(Debug, PartialEq, Eq)]
+    enum GameError {
+        /// Only an odd number of shapes lets every shape beat as many shapes as it loses to.
+        EvenShapeCount(usize),
+        /// With a single shape, every round is a draw.
+        TooFewShapes(usize),
+        ScoreCount {
+            shapes: usize,
+            scores: usize,
+        },
+        InvalidRound {
+            line: usize,
+        },
+        UnknownShape {
+            line: usize,
+            letter: char,
+        },
+        /// The interpretation doesn't say what the response letter means.
+        UnknownResponse {
+            line: usize,
+            letter: char,
+        },
+        /// The guide uses more response letters than the game has shapes to give them.
+        TooManyResponses {
+            letters: usize,
+            shapes: usize,
+        },
+    }
 
-                (&Self::SCISSORS, &Self::SCISSORS) => Outcome::DRAW,
-                (&Self::SCISSORS, &Self::ROCK) => Outcome::LOSS,
-                (&Self::SCISSORS, &Self::PAPER) => Outcome::WIN,
+    impl fmt::Display for GameError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                GameError::EvenShapeCount(count) => {
+                    write!(f, "a cyclic game can't have {count} shapes")
+                }
+                GameError::TooFewShapes(count) => {
+                    write!(f, "a cyclic game needs at least 3 shapes, not {count}")
+                }
+                GameError::ScoreCount { shapes, scores } => {
+                    write!(f, "{scores} scores for {shapes} shapes")
+                }
+                GameError::InvalidRound { line } => write!(f, "line {line}: not a round"),
+                GameError::UnknownShape { line, letter } => {
+                    write!(f, "line {line}: '{letter}' is not a shape")
+                }
+                GameError::UnknownResponse { line, letter } => {
+                    write!(f, "line {line}: '{letter}' has no meaning")
+                }
+                GameError::TooManyResponses { letters, shapes } => {
+                    write!(f, "{letters} response letters for {shapes} shapes")
+                }
             }
         }
+    }
+
+    impl Error for GameError {}
+
+    /// A hand game in which the shapes form a cycle: every shape beats the half of the others
+    /// that come right before it, and loses to the half that come right after it.
+    struct Game {
+        shapes: Vec<String>,
+        shape_scores: Vec<u32>,
+        /// The scores for a loss, a draw and a win.
+        outcome_scores: [u32; 3],
+    }
 
-        fn nemesis(&self) -> Choice {
-            match &self {
-                &Self::ROCK => Self::PAPER,
-                &Self::PAPER => Self::SCISSORS,
-                &Self::SCISSORS => Self::ROCK,
+    impl Game {
+        fn new(
+            shapes: &[&str],
+            shape_scores: &[u32],
+            outcome_scores: [u32; 3],
+        ) -> Result<Game, GameError> {
+            if shapes.len().is_multiple_of(2) {
+                Err(GameError::EvenShapeCount(shapes.len()))?
             }
+            if shapes.len() < 3 {
+                Err(GameError::TooFewShapes(shapes.len()))?
+            }
+            if shape_scores.len() != shapes.len() {
+                Err(GameError::ScoreCount {
+                    shapes: shapes.len(),
+                    scores: shape_scores.len(),
+                })?
+            }
+            Ok(Game {
+                shapes: shapes.iter().map(|shape| shape.to_string()).collect(),
+                shape_scores: shape_scores.to_vec(),
+                outcome_scores,
+            })
+        }
+
+        /// A game with the puzzle's scores: 1, 2, 3, ... for the shapes in order, 0, 3 and 6
+        /// for a loss, a draw and a win.
+        fn with_default_scores(shapes: &[&str]) -> Result<Game, GameError> {
+            let shape_scores = (1..=shapes.len() as u32).collect_vec();
+            Game::new(shapes, &shape_scores, [0, 3, 6])
         }
 
-        fn sub(&self) -> Choice {
-            match &self {
-                &Self::ROCK => Self::SCISSORS,
-                &Self::PAPER => Self::ROCK,
-                &Self::SCISSORS => Self::PAPER,
+        fn rock_paper_scissors() -> Game {
+            Game::with_default_scores(&["Rock", "Paper", "Scissors"]).unwrap()
+        }
+
+        fn rock_paper_scissors_lizard_spock() -> Game {
+            Game::with_default_scores(&["Rock", "Spock", "Paper", "Lizard", "Scissors"]).unwrap()
+        }
+
+        fn compare(&self, mine: Shape, theirs: Shape) -> Outcome {
+            let count = self.shapes.len();
+            match (mine + count - theirs) % count {
+                0 => Outcome::Draw,
+                distance if distance <= count / 2 => Outcome::Win,
+                _ => Outcome::Loss,
             }
         }
+
+        /// The shape to play for the given outcome. If several shapes would do, the one that
+        /// scores best. With at least 3 shapes there is always one.
+        fn shape_for(&self, theirs: Shape, outcome: Outcome) -> Shape {
+            (0..self.shapes.len())
+                .filter(|&mine| self.compare(mine, theirs) == outcome)
+                .rev()
+                .max_by_key(|&mine| self.shape_scores[mine])
+                .unwrap()
+        }
+
+        fn score(&self, mine: Shape, theirs: Shape) -> u32 {
+            self.shape_scores[mine] + self.outcome_scores[self.compare(mine, theirs) as usize]
+        }
     }
 
+    /// A line of the strategy guide: the opponent's shape, and the letter telling what to do.
     #[derive(Clone, Copy, Debug)]
-    enum Outcome {
-        WIN,
-        DRAW,
-        LOSS,
+    struct Round {
+        line: usize,
+        opponent: Shape,
+        response: char,
     }
 
-    impl Outcome {
-        fn score(&self) -> u32 {
-            match &self {
-                Self::WIN => 6,
-                Self::DRAW => 3,
-                Self::LOSS => 0,
+    /// Reads a strategy guide, the opponent's shapes lettered A, B, C, ... in cycle order.
+    fn parse_guide(input: &str, game: &Game) -> Result<Vec<Round>, GameError> {
+        input
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| {
+                let line_number = index + 1;
+                let chars = line.trim().chars().collect_vec();
+                let [opponent, ' ', response] = chars[..] else {
+                    Err(GameError::InvalidRound { line: line_number })?
+                };
+                let shape = (opponent as usize).wrapping_sub('A' as usize);
+                if shape >= game.shapes.len() {
+                    Err(GameError::UnknownShape {
+                        line: line_number,
+                        letter: opponent,
+                    })?
+                }
+                Ok(Round {
+                    line: line_number,
+                    opponent: shape,
+                    response,
+                })
+            })
+            .collect()
+    }
+
+    /// What the letters of the second column could mean, each letter meaning something else.
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    enum Interpretation {
+        Shapes(Vec<(char, Shape)>),
+        Outcomes(Vec<(char, Outcome)>),
+    }
+
+    impl Interpretation {
+        /// Every way to read the letters as different shapes, or as different outcomes.
+        fn all(game: &Game, letters: &[char]) -> Vec<Interpretation> {
+            let shapes = (0..game.shapes.len())
+                .permutations(letters.len())
+                .map(|shapes| {
+                    Interpretation::Shapes(letters.iter().copied().zip(shapes).collect())
+                });
+            let outcomes = OUTCOMES
+                .into_iter()
+                .permutations(letters.len())
+                .map(|outcomes| {
+                    Interpretation::Outcomes(letters.iter().copied().zip(outcomes).collect())
+                });
+            shapes.chain(outcomes).collect()
+        }
+
+        fn score(&self, game: &Game, guide: &[Round]) -> Result<u32, GameError> {
+            guide
+                .iter()
+                .map(|round| {
+                    let mine = match self {
+                        Interpretation::Shapes(shapes) => lookup(shapes, round)?,
+                        Interpretation::Outcomes(outcomes) => {
+                            game.shape_for(round.opponent, lookup(outcomes, round)?)
+                        }
+                    };
+                    Ok(game.score(mine, round.opponent))
+                })
+                .sum()
+        }
+
+        fn describe(&self, game: &Game) -> String {
+            match self {
+                Interpretation::Shapes(shapes) => shapes
+                    .iter()
+                    .map(|&(letter, shape)| format!("{letter} = {}", game.shapes[shape]))
+                    .join(", "),
+                Interpretation::Outcomes(outcomes) => outcomes
+                    .iter()
+                    .map(|(letter, outcome)| format!("{letter} = {outcome}"))
+                    .join(", "),
             }
         }
     }
 
-    #[test]
-    fn day2_part1() -> Result<(), Box<dyn std::error::Error>> {
-        fn parse(c: char) -> Choice {
-            match c {
-                'A' | 'X' => Choice::ROCK,
-                'B' | 'Y' => Choice::PAPER,
-                'C' | 'Z' => Choice::SCISSORS,
-                _ => panic!("Unknown character {}", c),
-            }
+    fn lookup<T: Copy>(mapping: &[(char, T)], round: &Round) -> Result<T, GameError> {
+        mapping
+            .iter()
+            .find(|(letter, _)| *letter == round.response)
+            .map(|&(_, meaning)| meaning)
+            .ok_or(GameError::UnknownResponse {
+                line: round.line,
+                letter: round.response,
+            })
+    }
+
+    /// Scores the guide under every interpretation, best first.
+    fn evaluate(game: &Game, guide: &[Round]) -> Result<Vec<(Interpretation, u32)>, GameError> {
+        let letters = guide
+            .iter()
+            .map(|round| round.response)
+            .sorted()
+            .dedup()
+            .collect_vec();
+        if letters.len() > game.shapes.len() {
+            Err(GameError::TooManyResponses {
+                letters: letters.len(),
+                shapes: game.shapes.len(),
+            })?
         }
+        let mut evaluated = Interpretation::all(game, &letters)
+            .into_iter()
+            .map(|interpretation| {
+                let score = interpretation.score(game, guide)?;
+                Ok((interpretation, score))
+            })
+            .collect::<Result<Vec<_>, GameError>>()?;
+        evaluated.sort_by_key(|(_, score)| std::cmp::Reverse(*score));
+        Ok(evaluated)
+    }
 
-        let input = get_input(2)?;
-        let mut total_score = 0;
-        for line in input.lines() {
-            let chars: Vec<char> = line.chars().collect();
-            let opponent = parse(chars[0]);
-            let choice = parse(chars[2]);
+    fn literal_shapes() -> Interpretation {
+        Interpretation::Shapes(vec![('X', 0), ('Y', 1), ('Z', 2)])
+    }
 
-            let comparison = choice.compare(&opponent);
+    fn literal_outcomes() -> Interpretation {
+        Interpretation::Outcomes(vec![
+            ('X', Outcome::Loss),
+            ('Y', Outcome::Draw),
+            ('Z', Outcome::Win),
+        ])
+    }
+
+    #[test]
+    fn day2_sample() -> Result<(), Box<dyn std::error::Error>> {
+        let game = Game::rock_paper_scissors();
+        let guide = parse_guide("A Y\nB X\nC Z", &game)?;
+        assert!(literal_shapes().score(&game, &guide)? == 15);
+        assert!(literal_outcomes().score(&game, &guide)? == 12);
+
+        let evaluated = evaluate(&game, &guide)?;
+        assert!(evaluated.len() == 12);
+        let (best, best_score) = evaluated.first().unwrap();
+        assert!(*best_score == 24);
+        assert!(best.describe(&game) == "X = Scissors, Y = Paper, Z = Rock");
+        let (worst, worst_score) = evaluated.last().unwrap();
+        assert!(*worst_score == 6);
+        assert!(worst.describe(&game) == "X = Rock, Y = Scissors, Z = Paper");
 
-            let score = comparison.score() + choice.score();
-            total_score += score;
-        }
-        println!("total score: {}", total_score);
         Ok(())
     }
 
     #[test]
-    fn day2_part2() -> Result<(), Box<dyn std::error::Error>> {
-        fn parse_opponent(c: char) -> Choice {
-            match c {
-                'A' => Choice::ROCK,
-                'B' => Choice::PAPER,
-                'C' => Choice::SCISSORS,
-                _ => panic!("Unknown character {}", c),
-            }
+    fn day2_rules() -> Result<(), Box<dyn std::error::Error>> {
+        let game = Game::rock_paper_scissors_lizard_spock();
+        let shape = |name| game.shapes.iter().position(|s| s == name).unwrap();
+        for (winner, loser) in [
+            ("Scissors", "Paper"),
+            ("Paper", "Rock"),
+            ("Rock", "Lizard"),
+            ("Lizard", "Spock"),
+            ("Spock", "Scissors"),
+            ("Scissors", "Lizard"),
+            ("Lizard", "Paper"),
+            ("Paper", "Spock"),
+            ("Spock", "Rock"),
+            ("Rock", "Scissors"),
+        ] {
+            assert!(game.compare(shape(winner), shape(loser)) == Outcome::Win);
+            assert!(game.compare(shape(loser), shape(winner)) == Outcome::Loss);
         }
+        // both Paper and Lizard beat Spock, Lizard scores more
+        assert!(game.shape_for(shape("Spock"), Outcome::Win) == shape("Lizard"));
 
-        fn parse_outcome(c: char) -> Outcome {
-            match c {
-                'X' => Outcome::LOSS,
-                'Y' => Outcome::DRAW,
-                'Z' => Outcome::WIN,
-                _ => panic!("Unknown character {}", c),
-            }
-        }
+        let guide = parse_guide("E V\nA W\nD V", &game)?;
+        let evaluated = evaluate(&game, &guide)?;
+        assert!(evaluated.len() == 5 * 4 + 3 * 2);
 
-        let input = get_input(2)?;
-        let mut total_score = 0;
-        for line in input.lines() {
-            let chars: Vec<char> = line.chars().collect();
-            let opponent = parse_opponent(chars[0]);
-            let expected_outcome = parse_outcome(chars[2]);
-
-            let required_choice = match expected_outcome {
-                Outcome::WIN => opponent.nemesis(),
-                Outcome::DRAW => opponent,
-                Outcome::LOSS => opponent.sub(),
-            };
-
-            let score = expected_outcome.score() + required_choice.score();
-            total_score += score;
-        }
-        println!("total score: {}", total_score);
+        let game = Game::new(&["Rock", "Paper", "Scissors"], &[3, 2, 1], [1, 2, 4])?;
+        assert!(game.score(0, 2) == 7);
+        assert!(game.score(2, 0) == 2);
+        assert!(game.shape_for(1, Outcome::Draw) == 1);
+
+        assert!(
+            Game::with_default_scores(&["Rock", "Paper"]).err()
+                == Some(GameError::EvenShapeCount(2))
+        );
+        assert!(Game::with_default_scores(&["Rock"]).err() == Some(GameError::TooFewShapes(1)));
+        assert!(
+            Game::new(&["Rock", "Paper", "Scissors"], &[1, 2], [0, 3, 6]).err()
+                == Some(GameError::ScoreCount {
+                    shapes: 3,
+                    scores: 2
+                })
+        );
+        let rps = Game::rock_paper_scissors();
+        assert!(
+            parse_guide("A X\nD Y", &rps).unwrap_err()
+                == GameError::UnknownShape {
+                    line: 2,
+                    letter: 'D'
+                }
+        );
+        assert!(parse_guide("AX", &rps).unwrap_err() == GameError::InvalidRound { line: 1 });
+
+        let guide = parse_guide("A X\n\nB W", &rps)?;
+        assert!(
+            literal_shapes().score(&rps, &guide).unwrap_err()
+                == GameError::UnknownResponse {
+                    line: 3,
+                    letter: 'W'
+                }
+        );
+        assert!(evaluate(&rps, &guide)?.len() == 6 + 6);
+        let guide = parse_guide("A W\nA X\nA Y\nA Z", &rps)?;
+        assert!(
+            evaluate(&rps, &guide).unwrap_err()
+                == GameError::TooManyResponses {
+                    letters: 4,
+                    shapes: 3
+                }
+        );
+        // more letters than outcomes still leaves the shapes to choose from
+        let game = Game::rock_paper_scissors_lizard_spock();
+        let guide = parse_guide("A V\nA W\nA X\nA Y", &game)?;
+        assert!(evaluate(&game, &guide)?.len() == 5 * 4 * 3 * 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn day2_part1() -> Result<(), Box<dyn std::error::Error>> {
+        let game = Game::rock_paper_scissors();
+        let guide = parse_guide(&get_input(2)?, &game)?;
+        println!("total score: {}", literal_shapes().score(&game, &guide)?);
+        Ok(())
+    }
+
+    #[test]
+    fn day2_part2() -> Result<(), Box<dyn std::error::Error>> {
+        let game = Game::rock_paper_scissors();
+        let guide = parse_guide(&get_input(2)?, &game)?;
+        println!("total score: {}", literal_outcomes().score(&game, &guide)?);
+        Ok(())
+    }
+
+    #[test]
+    fn day2_interpretations() -> Result<(), Box<dyn std::error::Error>> {
+        let game = Game::rock_paper_scissors();
+        let guide = parse_guide(&get_input(2)?, &game)?;
+        let evaluated = evaluate(&game, &guide)?;
+        let (best, best_score) = evaluated.first().unwrap();
+        let (worst, worst_score) = evaluated.last().unwrap();
+        println!("best: {} scores {best_score}", best.describe(&game));
+        println!("worst: {} scores {worst_score}", worst.describe(&game));
         Ok(())
     }
 }
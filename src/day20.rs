@@ -5,7 +5,166 @@ mod tests {
     use itertools::Itertools;
     use num::Integer;
 
-    use crate::common::tests::{get_input, get_sample_input};
+    use crate::common::tests::{get_input, get_sample_input, pseudo_random};
+
+    /// A circular sequence of values that supports moving elements around in O(sqrt(n)).
+    ///
+    /// Elements are identified by their original index. The order is kept as a list of blocks
+    /// of ids, and every id remembers the block it's in, so finding the position of an element
+    /// only needs a scan over the block sizes and over a single block.
+    struct CircularSequence<T> {
+        values: Vec<T>,
+        blocks: Vec<Vec<usize>>,
+        block_of: Vec<usize>,
+        block_size: usize,
+        moves_since_rebuild: usize,
+    }
+
+    impl<T> CircularSequence<T> {
+        fn new(values: Vec<T>) -> CircularSequence<T> {
+            let block_size = ((values.len() as f64).sqrt() as usize).max(1);
+            let mut sequence = CircularSequence {
+                blocks: vec![(0..values.len()).collect()],
+                block_of: vec![0; values.len()],
+                values,
+                block_size,
+                moves_since_rebuild: 0,
+            };
+            sequence.rebuild();
+            sequence
+        }
+
+        fn len(&self) -> usize {
+            self.values.len()
+        }
+
+        fn value(&self, id: usize) -> &T {
+            &self.values[id]
+        }
+
+        /// Splits the order back into blocks of equal size.
+        fn rebuild(&mut self) {
+            let order = self.blocks.concat();
+            self.blocks = order
+                .chunks(self.block_size)
+                .map(|chunk| chunk.to_vec())
+                .collect();
+            for (block_index, block) in self.blocks.iter().enumerate() {
+                for &id in block {
+                    self.block_of[id] = block_index;
+                }
+            }
+            self.moves_since_rebuild = 0;
+        }
+
+        /// The current position of the element with the given id.
+        fn position(&self, id: usize) -> usize {
+            let block_index = self.block_of[id];
+            let before: usize = self.blocks[..block_index].iter().map(|b| b.len()).sum();
+            before
+                + self.blocks[block_index]
+                    .iter()
+                    .position(|&i| i == id)
+                    .unwrap()
+        }
+
+        /// The block and the offset in that block for a position.
+        fn locate(&self, mut index: usize) -> (usize, usize) {
+            for (block_index, block) in self.blocks.iter().enumerate() {
+                if index < block.len() {
+                    return (block_index, index);
+                }
+                index -= block.len();
+            }
+            let last = self.blocks.len() - 1;
+            (last, self.blocks[last].len())
+        }
+
+        /// The id of the element at the given position, wrapping around.
+        fn id_at(&self, index: usize) -> usize {
+            let (block_index, offset) = self.locate(index % self.len());
+            self.blocks[block_index][offset]
+        }
+
+        /// Moves an element `offset` places forward (or backward, if negative) in the circle.
+        /// As the element itself isn't part of the circle it moves through, a full turn is `len() - 1` places.
+        fn move_by(&mut self, id: usize, offset: i64) {
+            if self.len() < 2 {
+                return;
+            }
+            let from = self.position(id);
+            let to = (from as i64 + offset).mod_floor(&(self.len() as i64 - 1)) as usize;
+            if from == to {
+                return;
+            }
+
+            let block_index = self.block_of[id];
+            self.blocks[block_index].retain(|&i| i != id);
+
+            let (block_index, offset) = self.locate(to);
+            self.blocks[block_index].insert(offset, id);
+            self.block_of[id] = block_index;
+
+            self.moves_since_rebuild += 1;
+            if self.moves_since_rebuild >= self.block_size {
+                self.rebuild();
+            }
+        }
+
+        fn iter(&self) -> impl Iterator<Item = &T> + '_ {
+            self.blocks.iter().flatten().map(|&id| &self.values[id])
+        }
+    }
+
+    fn parse(input: &str, decryption_key: i64) -> Result<Vec<i64>, Box<dyn Error>> {
+        let mut numbers = Vec::new();
+        for line in input.lines() {
+            numbers.push(line.parse::<i64>()? * decryption_key);
+        }
+        Ok(numbers)
+    }
+
+    fn mix(numbers: Vec<i64>, iteration_count: usize) -> CircularSequence<i64> {
+        let mut sequence = CircularSequence::new(numbers);
+        for _ in 0..iteration_count {
+            for id in 0..sequence.len() {
+                let offset = *sequence.value(id);
+                sequence.move_by(id, offset);
+            }
+        }
+        sequence
+    }
+
+    fn grove_coordinates(sequence: &CircularSequence<i64>) -> Result<i64, Box<dyn Error>> {
+        let zero = (0..sequence.len())
+            .find(|&id| *sequence.value(id) == 0)
+            .ok_or("No zero in the sequence")?;
+        let zero_index = sequence.position(zero);
+
+        Ok([1000, 2000, 3000]
+            .iter()
+            .map(|offset| *sequence.value(sequence.id_at(zero_index + offset)))
+            .sum())
+    }
+
+    fn decrypt(
+        input: &str,
+        decryption_key: i64,
+        iteration_count: usize,
+    ) -> Result<i64, Box<dyn Error>> {
+        let numbers = parse(input, decryption_key)?;
+        grove_coordinates(&mix(numbers, iteration_count))
+    }
+
+    /// The sequence rotated so that it starts at the zero.
+    fn starting_at_zero(numbers: &[i64]) -> Vec<i64> {
+        let zero_index = numbers.iter().position(|n| n == &0).unwrap();
+        numbers[zero_index..]
+            .iter()
+            .chain(numbers[..zero_index].iter())
+            .copied()
+            .collect()
+    }
 
     #[test]
     fn day20_part1_sample() -> Result<(), Box<dyn Error>> {
@@ -27,6 +186,38 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn day20_matches_naive_mixing() -> Result<(), Box<dyn Error>> {
+        let numbers = pseudo_random(12345)
+            .take(1000)
+            .enumerate()
+            .map(|(i, random)| {
+                if i == 500 {
+                    0
+                } else {
+                    random as i64 % 20_000 - 10_000
+                }
+            })
+            .collect_vec();
+        let input = numbers.iter().join("\n");
+
+        for (decryption_key, iteration_count) in [(1, 1), (811589153, 3)] {
+            assert_eq!(
+                decrypt(&input, decryption_key, iteration_count)?,
+                decrypt_naive(&input, decryption_key, iteration_count)?
+            );
+
+            let mixed = mix(parse(&input, decryption_key)?, iteration_count);
+            let naive = mix_naive(&input, decryption_key, iteration_count);
+            assert_eq!(
+                starting_at_zero(&mixed.iter().copied().collect_vec()),
+                starting_at_zero(&naive)
+            );
+        }
+
+        Ok(())
+    }
+
     #[test]
     fn day20_part1() -> Result<(), Box<dyn Error>> {
         let input = get_input(20)?;
@@ -46,11 +237,8 @@ mod tests {
         Ok(())
     }
 
-    fn decrypt(
-        input: &str,
-        decryption_key: i64,
-        iteration_count: usize,
-    ) -> Result<i64, Box<dyn Error>> {
+    /// The original O(n²) mixing, only kept as an oracle for the tests.
+    fn mix_naive(input: &str, decryption_key: i64, iteration_count: usize) -> Vec<i64> {
         let mut numbers = input
             .lines()
             .enumerate()
@@ -73,7 +261,6 @@ mod tests {
                 if !processed {
                     let new_index = (i as i64 + number).mod_floor(&(count as i64 - 1));
                     let mut new_index = new_index as usize;
-                    // println!("processing {number}: {i} -> {new_index}");
                     if new_index == i {
                         // nothing to do, just mark as processed and go on
                         numbers.get_mut(i).unwrap().2 = true;
@@ -88,15 +275,21 @@ mod tests {
             }
         }
 
-        let count = count as usize;
-        let zero_index = numbers
-            .iter()
-            .position(|(number, _, _)| number == &0)
-            .unwrap();
+        numbers.into_iter().map(|(number, _, _)| number).collect()
+    }
+
+    fn decrypt_naive(
+        input: &str,
+        decryption_key: i64,
+        iteration_count: usize,
+    ) -> Result<i64, Box<dyn Error>> {
+        let numbers = mix_naive(input, decryption_key, iteration_count);
+        let count = numbers.len();
+        let zero_index = numbers.iter().position(|number| number == &0).unwrap();
 
-        let result = numbers[(zero_index + 1000) % count].0
-            + numbers[(zero_index + 2000) % count].0
-            + numbers[(zero_index + 3000) % count].0;
+        let result = numbers[(zero_index + 1000) % count]
+            + numbers[(zero_index + 2000) % count]
+            + numbers[(zero_index + 3000) % count];
 
         Ok(result)
     }
@@ -1,82 +1,354 @@
 #[cfg(test)]
 mod tests {
 
-    use crate::common::tests::get_input;
-    use std::collections::HashSet;
+    use crate::common::tests::{get_input, get_sample_input};
+    use std::{
+        error::Error,
+        fmt,
+        ops::{BitAnd, BitOr},
+    };
 
     use itertools::Itertools;
 
-    fn day3_priority(c: char) -> u32 {
+    fn day3_priority(c: char) -> Option<u32> {
         if c.is_ascii_lowercase() {
-            (c as u32) - 97 + 1 //a is ascii code 97
+            Some((c as u32) - 97 + 1) //a is ascii code 97
         } else if c.is_ascii_uppercase() {
-            (c as u32) - 65 + 27 // A is ascii code 65
+            Some((c as u32) - 65 + 27) // A is ascii code 65
         } else {
-            panic!("unsupported char");
+            None
         }
     }
 
-    #[test]
-    fn day3_part1() -> Result<(), Box<dyn std::error::Error>> {
-        let input = get_input(3)?;
-        let mut priority_sum = 0;
-        for line in input.lines() {
-            let all_chars = line.chars().collect_vec();
-            let mut first_chars_set: HashSet<char> = HashSet::new();
-            let mut second_chars_set: HashSet<char> = HashSet::new();
-            for char in &all_chars[0..all_chars.len() / 2] {
-                first_chars_set.insert(*char);
-            }
+    fn day3_item(priority: u32) -> char {
+        match priority {
+            1..=26 => char::from_u32(priority - 1 + 97).unwrap(),
+            _ => char::from_u32(priority - 27 + 65).unwrap(),
+        }
+    }
+
+    /// A set of item types, one bit per priority.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    struct Items(u64);
+
+    impl Items {
+        const ALL: Items = Items((1 << 52) - 1);
+
+        fn parse(items: &str) -> Result<Items, char> {
+            items.chars().try_fold(Items::default(), |set, c| {
+                let priority = day3_priority(c).ok_or(c)?;
+                Ok(set | Items(1 << (priority - 1)))
+            })
+        }
 
-            for char in &all_chars[all_chars.len() / 2..] {
-                second_chars_set.insert(*char);
+        fn len(&self) -> u32 {
+            self.0.count_ones()
+        }
+
+        fn iter(&self) -> impl Iterator<Item = char> {
+            let bits = self.0;
+            (1..=52)
+                .filter(move |priority| bits & 1 << (priority - 1) != 0)
+                .map(day3_item)
+        }
+
+        fn priority_sum(&self) -> u32 {
+            (1..=52)
+                .filter(|priority| self.0 & 1 << (priority - 1) != 0)
+                .sum()
+        }
+
+        /// The single item in the set.
+        fn single(&self) -> Option<char> {
+            match self.len() {
+                1 => self.iter().next(),
+                _ => None,
             }
+        }
+    }
 
-            let common_chars: Vec<char> = first_chars_set
-                .intersection(&second_chars_set)
-                .copied()
-                .collect();
-            let common_char = common_chars[0];
-            let priority = day3_priority(common_char);
-            priority_sum += priority;
+    impl BitAnd for Items {
+        type Output = Items;
+
+        fn bitand(self, other: Items) -> Items {
+            Items(self.0 & other.0)
         }
-        println!("Total priority: {}", priority_sum);
-        Ok(())
     }
 
-    #[test]
-    fn day3_part2() -> Result<(), Box<dyn std::error::Error>> {
-        let input = get_input(3)?;
-        let mut priority_sum = 0;
-        for line_triple_iter in &input.lines().chunks(3) {
-            let line_triple = line_triple_iter.collect_vec();
-            let mut first_chars_set: HashSet<char> = HashSet::new();
-            let mut second_chars_set: HashSet<char> = HashSet::new();
-            let mut third_chars_set: HashSet<char> = HashSet::new();
-            for c in line_triple[0].chars().collect_vec() {
-                first_chars_set.insert(c);
+    impl BitOr for Items {
+        type Output = Items;
+
+        fn bitor(self, other: Items) -> Items {
+            Items(self.0 | other.0)
+        }
+    }
+
+    impl fmt::Display for Items {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{}", self.iter().collect::<String>())
+        }
+    }
+
+    #[derive(Debug, PartialEq, Eq)]
+    enum RucksackError {
+        OddLength {
+            line: usize,
+        },
+        InvalidItem {
+            line: usize,
+            item: char,
+        },
+        /// The compartments of a rucksack don't share exactly one item.
+        NoSingleDuplicate {
+            line: usize,
+            duplicates: String,
+        },
+        /// The rucksacks of a group don't share exactly one item.
+        NoSingleCommonItem {
+            group: usize,
+            common: String,
+        },
+        IncompleteGroup {
+            group: usize,
+        },
+        NoSuchRucksack {
+            group: usize,
+            rucksack: usize,
+        },
+    }
+
+    impl fmt::Display for RucksackError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                RucksackError::OddLength { line } => {
+                    write!(f, "line {line}: the compartments can't be equally full")
+                }
+                RucksackError::InvalidItem { line, item } => {
+                    write!(f, "line {line}: '{item}' is not an item")
+                }
+                RucksackError::NoSingleDuplicate { line, duplicates } => {
+                    write!(f, "line {line}: both compartments hold {duplicates:?}")
+                }
+                RucksackError::NoSingleCommonItem { group, common } => {
+                    write!(f, "group {group} shares {common:?} instead of one item")
+                }
+                RucksackError::IncompleteGroup { group } => {
+                    write!(f, "group {group} is incomplete")
+                }
+                RucksackError::NoSuchRucksack { group, rucksack } => {
+                    write!(f, "group {group} refers to a missing rucksack {rucksack}")
+                }
             }
-            for c in line_triple[1].chars().collect_vec() {
-                second_chars_set.insert(c);
+        }
+    }
+
+    impl Error for RucksackError {}
+
+    #[derive(Clone, Copy, Debug)]
+    struct Rucksack {
+        compartments: [Items; 2],
+    }
+
+    impl Rucksack {
+        fn parse(line: &str, line_number: usize) -> Result<Rucksack, RucksackError> {
+            let parse = |items| {
+                Items::parse(items).map_err(|item| RucksackError::InvalidItem {
+                    line: line_number,
+                    item,
+                })
+            };
+            // only an ASCII line can be split in half by byte
+            if let Some(item) = line.chars().find(|c| !c.is_ascii()) {
+                Err(RucksackError::InvalidItem {
+                    line: line_number,
+                    item,
+                })?
             }
-            for c in line_triple[2].chars().collect_vec() {
-                third_chars_set.insert(c);
+            if !line.len().is_multiple_of(2) {
+                Err(RucksackError::OddLength { line: line_number })?
             }
+            let (first, second) = line.split_at(line.len() / 2);
+            Ok(Rucksack {
+                compartments: [parse(first)?, parse(second)?],
+            })
+        }
 
-            let first_intersection: HashSet<char> = first_chars_set
-                .intersection(&second_chars_set)
-                .copied()
-                .collect();
-            let common_chars: Vec<char> = first_intersection
-                .intersection(&third_chars_set)
-                .copied()
-                .collect();
-
-            let common_char = common_chars[0];
-            let priority = day3_priority(common_char);
-            priority_sum += priority;
+        fn items(&self) -> Items {
+            self.compartments[0] | self.compartments[1]
         }
-        println!("Total priority: {}", priority_sum);
+
+        /// The items that were packed in both compartments.
+        fn duplicates(&self) -> Items {
+            self.compartments[0] & self.compartments[1]
+        }
+    }
+
+    fn parse_rucksacks(input: &str) -> Result<Vec<Rucksack>, RucksackError> {
+        input
+            .lines()
+            .enumerate()
+            .map(|(index, line)| Rucksack::parse(line.trim(), index + 1))
+            .collect()
+    }
+
+    /// The sum of the priorities of the item each rucksack has in both compartments.
+    fn duplicate_priority_sum(rucksacks: &[Rucksack]) -> Result<u32, RucksackError> {
+        let mut sum = 0;
+        for (index, rucksack) in rucksacks.iter().enumerate() {
+            let duplicates = rucksack.duplicates();
+            duplicates
+                .single()
+                .ok_or_else(|| RucksackError::NoSingleDuplicate {
+                    line: index + 1,
+                    duplicates: duplicates.to_string(),
+                })?;
+            sum += duplicates.priority_sum();
+        }
+        Ok(sum)
+    }
+
+    /// The single item shared by all rucksacks of every group, the groups given as lists of
+    /// rucksack indices.
+    fn badges(rucksacks: &[Rucksack], groups: &[Vec<usize>]) -> Result<Vec<char>, RucksackError> {
+        groups
+            .iter()
+            .enumerate()
+            .map(|(group, members)| {
+                let mut common = Items::ALL;
+                for &rucksack in members {
+                    let items = rucksacks
+                        .get(rucksack)
+                        .ok_or(RucksackError::NoSuchRucksack { group, rucksack })?
+                        .items();
+                    common = common & items;
+                }
+                common
+                    .single()
+                    .filter(|_| !members.is_empty())
+                    .ok_or_else(|| RucksackError::NoSingleCommonItem {
+                        group,
+                        common: common.to_string(),
+                    })
+            })
+            .collect()
+    }
+
+    /// Consecutive groups of the given size.
+    fn consecutive_groups(
+        rucksack_count: usize,
+        group_size: usize,
+    ) -> Result<Vec<Vec<usize>>, RucksackError> {
+        assert!(group_size > 0, "Groups can't be empty");
+        (0..rucksack_count)
+            .chunks(group_size)
+            .into_iter()
+            .enumerate()
+            .map(|(group, members)| {
+                let members = members.collect_vec();
+                match members.len() == group_size {
+                    true => Ok(members),
+                    false => Err(RucksackError::IncompleteGroup { group }),
+                }
+            })
+            .collect()
+    }
+
+    fn badge_priority_sum(badges: &[char]) -> u32 {
+        badges
+            .iter()
+            .filter_map(|&badge| day3_priority(badge))
+            .sum()
+    }
+
+    #[test]
+    fn day3_sample() -> Result<(), Box<dyn std::error::Error>> {
+        let rucksacks = parse_rucksacks(&get_sample_input(3)?)?;
+        assert!(duplicate_priority_sum(&rucksacks)? == 157);
+        let duplicates = rucksacks
+            .iter()
+            .map(|r| r.duplicates().to_string())
+            .join("");
+        assert!(duplicates == "pLPvts");
+
+        let groups = consecutive_groups(rucksacks.len(), 3)?;
+        let badges = badges(&rucksacks, &groups)?;
+        assert!(badges == vec!['r', 'Z']);
+        assert!(badge_priority_sum(&badges) == 70);
+
+        Ok(())
+    }
+
+    #[test]
+    fn day3_groups_and_errors() -> Result<(), Box<dyn std::error::Error>> {
+        let rucksacks = parse_rucksacks(&get_sample_input(3)?)?;
+
+        // pairs of rucksacks share more than one item, so they have no badge
+        let pairs = consecutive_groups(rucksacks.len(), 2)?;
+        assert!(matches!(
+            badges(&rucksacks, &pairs),
+            Err(RucksackError::NoSingleCommonItem { group: 0, .. })
+        ));
+        assert!(
+            consecutive_groups(rucksacks.len(), 4)
+                == Err(RucksackError::IncompleteGroup { group: 1 })
+        );
+
+        // any partition works, as long as every group shares one item
+        let partition = vec![vec![0, 1, 2], vec![5, 3, 4]];
+        assert!(badges(&rucksacks, &partition)? == vec!['r', 'Z']);
+        let everyone = vec![(0..rucksacks.len()).collect_vec()];
+        assert!(matches!(
+            badges(&rucksacks, &everyone),
+            Err(RucksackError::NoSingleCommonItem { common, .. }) if common.is_empty()
+        ));
+        assert!(
+            badges(&rucksacks, &[vec![0, 6]])
+                == Err(RucksackError::NoSuchRucksack {
+                    group: 0,
+                    rucksack: 6
+                })
+        );
+
+        let rucksack = Rucksack::parse("abcaBCab", 1)?;
+        assert!(rucksack.duplicates().to_string() == "ab");
+        assert!(
+            duplicate_priority_sum(&[rucksack]).unwrap_err()
+                == RucksackError::NoSingleDuplicate {
+                    line: 1,
+                    duplicates: "ab".to_string()
+                }
+        );
+
+        assert!(parse_rucksacks("abc").unwrap_err() == RucksackError::OddLength { line: 1 });
+        assert!(
+            parse_rucksacks("aa\na1").unwrap_err()
+                == RucksackError::InvalidItem { line: 2, item: '1' }
+        );
+        assert!(
+            parse_rucksacks("aéb").unwrap_err()
+                == RucksackError::InvalidItem {
+                    line: 1, item: 'é'
+                }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn day3_part1() -> Result<(), Box<dyn std::error::Error>> {
+        let input = get_input(3)?;
+        let rucksacks = parse_rucksacks(&input)?;
+        println!("Total priority: {}", duplicate_priority_sum(&rucksacks)?);
+        Ok(())
+    }
+
+    #[test]
+    fn day3_part2() -> Result<(), Box<dyn std::error::Error>> {
+        let input = get_input(3)?;
+        let rucksacks = parse_rucksacks(&input)?;
+        let groups = consecutive_groups(rucksacks.len(), 3)?;
+        let badges = badges(&rucksacks, &groups)?;
+        println!("Total priority: {}", badge_priority_sum(&badges));
         Ok(())
     }
 }
@@ -1,99 +1,605 @@
 #[cfg(test)]
 mod tests {
 
-    use crate::common::tests::get_input;
+    use crate::common::tests::{get_input, get_sample_input};
+
+    use std::{
+        collections::{HashMap, VecDeque},
+        error::Error,
+        fmt,
+        str::FromStr,
+    };
 
     use itertools::Itertools;
     use regex::Regex;
 
-    #[derive(Clone, Debug)]
+    /// Stacks of crates, bottom first.
+    #[derive(Clone, Debug, PartialEq, Eq, Hash)]
     struct Depot {
-        stacks: [Vec<char>; 9],
+        stacks: Vec<Vec<char>>,
     }
 
-    enum Day5Mode {
-        OneByOne,
-        AllAtOnce,
+    impl Depot {
+        /// The crates on top of the stacks, skipping empty stacks.
+        fn top_crates(&self) -> String {
+            self.stacks
+                .iter()
+                .filter_map(|stack| stack.last())
+                .collect()
+        }
     }
 
-    #[test]
-    fn day5_part1() -> Result<(), Box<dyn std::error::Error>> {
-        day5(Day5Mode::OneByOne)
+    impl FromStr for Depot {
+        type Err = Box<dyn Error>;
+
+        /// Reads the drawing of the puzzle. The numbered labels on the last line decide how many
+        /// stacks there are, and which column every stack is drawn in.
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let lines = s
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .collect_vec();
+            let (labels, rows) = lines.split_last().ok_or("Empty drawing")?;
+
+            let mut columns = Vec::new();
+            let mut previous = ' ';
+            for (column, c) in labels.char_indices() {
+                if !c.is_whitespace() && previous.is_whitespace() {
+                    let label = labels[column..].split_whitespace().next().unwrap();
+                    if label.parse::<usize>()? != columns.len() + 1 {
+                        Err(format!("Unexpected stack label {label}"))?
+                    }
+                    columns.push(column);
+                }
+                previous = c;
+            }
+
+            let mut stacks = vec![Vec::new(); columns.len()];
+            let mut ended = vec![false; columns.len()];
+            for row in rows.iter().rev() {
+                let row = row.chars().collect_vec();
+                let mut drawn = vec![false; row.len()];
+                for (number, &column) in columns.iter().enumerate() {
+                    match row.get(column) {
+                        None | Some(' ') => ended[number] = true,
+                        Some(&c) => {
+                            if column == 0
+                                || row.get(column - 1) != Some(&'[')
+                                || row.get(column + 1) != Some(&']')
+                            {
+                                Err(format!("Badly drawn crate on stack {}", number + 1))?
+                            }
+                            if ended[number] {
+                                Err(format!("Stack {} is floating", number + 1))?
+                            }
+                            stacks[number].push(c);
+                            drawn[column - 1..=column + 1].fill(true);
+                        }
+                    }
+                }
+                if let Some((c, _)) = row
+                    .iter()
+                    .zip(&drawn)
+                    .find(|(c, &drawn)| !drawn && !c.is_whitespace())
+                {
+                    Err(format!("Unexpected '{c}' in the drawing"))?
+                }
+            }
+            Ok(Depot { stacks })
+        }
     }
 
-    #[test]
-    fn day5_part2() -> Result<(), Box<dyn std::error::Error>> {
-        day5(Day5Mode::AllAtOnce)
-    }
-
-    fn day5(mode: Day5Mode) -> Result<(), Box<dyn std::error::Error>> {
-        let input = get_input(5)?;
-
-        let mut depot = Depot {
-            stacks: [
-                Vec::new(),
-                Vec::new(),
-                Vec::new(),
-                Vec::new(),
-                Vec::new(),
-                Vec::new(),
-                Vec::new(),
-                Vec::new(),
-                Vec::new(),
-            ],
-        };
+    impl fmt::Display for Depot {
+        /// Draws the depot like the puzzle does.
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let height = self
+                .stacks
+                .iter()
+                .map(|stack| stack.len())
+                .max()
+                .unwrap_or(0);
+            for level in (0..height).rev() {
+                let row = self
+                    .stacks
+                    .iter()
+                    .map(|stack| match stack.get(level) {
+                        Some(c) => format!("[{c}]"),
+                        None => "   ".to_string(),
+                    })
+                    .join(" ");
+                writeln!(f, "{row}")?;
+            }
+            let labels = (1..=self.stacks.len())
+                .map(|number| format!(" {number:<2}"))
+                .join(" ");
+            write!(f, "{labels}")
+        }
+    }
 
-        let mut lines = input.lines().into_iter();
+    /// A `move N from A to B` step, with the stacks counted from 0.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    struct Move {
+        count: usize,
+        from: usize,
+        to: usize,
+    }
 
-        // process the header of the input until we encounter an empty line
-        loop {
-            let line = lines.next().unwrap();
+    impl fmt::Display for Move {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(
+                f,
+                "move {} from {} to {}",
+                self.count,
+                self.from + 1,
+                self.to + 1
+            )
+        }
+    }
 
-            if line.starts_with(" 1") {
-                continue;
+    fn parse_moves(input: &str) -> Result<Vec<Move>, Box<dyn Error>> {
+        let r = Regex::new("^move (\\d+) from (\\d+) to (\\d+)$")?;
+        let mut moves = Vec::new();
+        for line in input.lines().filter(|line| !line.trim().is_empty()) {
+            let c = r
+                .captures(line.trim())
+                .ok_or_else(|| format!("Not a move: {line}"))?;
+            let stack = |i: usize| -> Result<usize, Box<dyn Error>> {
+                c[i].parse::<usize>()?
+                    .checked_sub(1)
+                    .ok_or_else(|| format!("No stack 0 in {line}").into())
+            };
+            moves.push(Move {
+                count: c[1].parse()?,
+                from: stack(2)?,
+                to: stack(3)?,
+            });
+        }
+        Ok(moves)
+    }
+
+    /// The drawing and the moves that follow it.
+    fn parse_input(input: &str) -> Result<(Depot, Vec<Move>), Box<dyn Error>> {
+        let (drawing, moves) = input
+            .split_once("\n\n")
+            .ok_or("No empty line after the drawing")?;
+        Ok((drawing.parse()?, parse_moves(moves)?))
+    }
+
+    #[derive(Debug, PartialEq, Eq)]
+    enum MoveError {
+        NoSuchStack(usize),
+        NotEnoughCrates { stack: usize, available: usize },
+    }
+
+    impl fmt::Display for MoveError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                MoveError::NoSuchStack(stack) => write!(f, "there is no stack {}", stack + 1),
+                MoveError::NotEnoughCrates { stack, available } => {
+                    write!(f, "stack {} only has {available} crates", stack + 1)
+                }
             }
-            if line.is_empty() {
-                break;
+        }
+    }
+
+    impl Error for MoveError {}
+
+    /// The crane models, which differ in how many crates they can pick up at once.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    enum Crane {
+        /// One crate at a time, so the moved crates end up in reverse order.
+        CrateMover9000,
+        /// Any number of crates at once, keeping their order.
+        CrateMover9001,
+        /// Up to the given number of crates at once.
+        AtMost(usize),
+    }
+
+    impl Crane {
+        fn capacity(&self) -> usize {
+            match *self {
+                Crane::CrateMover9000 => 1,
+                Crane::CrateMover9001 => usize::MAX,
+                Crane::AtMost(capacity) => capacity.max(1),
             }
+        }
 
-            for i in 0..9 {
-                if let Some(c) = line.chars().nth(1 + (4 * i)) {
-                    if c != ' ' {
-                        depot.stacks[i].insert(0, c);
-                    }
+        fn check(&self, depot: &Depot, step: &Move) -> Result<(), MoveError> {
+            for stack in [step.from, step.to] {
+                if stack >= depot.stacks.len() {
+                    Err(MoveError::NoSuchStack(stack))?
                 }
             }
+            let available = depot.stacks[step.from].len();
+            if available < step.count {
+                Err(MoveError::NotEnoughCrates {
+                    stack: step.from,
+                    available,
+                })?
+            }
+            Ok(())
         }
 
-        // process the remainder of the lines
-        let r = Regex::new("move (\\d+) from (\\d+) to (\\d+)")?;
-        for line in lines {
-            let c = r.captures(&line).unwrap();
-            let count = c[1].parse::<usize>()?;
-            let from = c[2].parse::<usize>()? - 1;
-            let to = c[3].parse::<usize>()? - 1;
+        /// Picks up as many crates as the crane can take from the top of a stack, and puts them
+        /// down on another one. Returns the number of crates moved.
+        fn lift(&self, depot: &mut Depot, from: usize, to: usize, remaining: usize) -> usize {
+            let count = remaining.min(self.capacity());
+            let from_stack = &mut depot.stacks[from];
+            let mut lifted = from_stack.split_off(from_stack.len() - count);
+            depot.stacks[to].append(&mut lifted);
+            count
+        }
 
-            match mode {
-                Day5Mode::OneByOne => {
-                    for _ in 0..count {
-                        let popped = depot.stacks[from].pop().unwrap();
-                        depot.stacks[to].push(popped);
-                    }
+        fn apply(&self, depot: &mut Depot, step: &Move) -> Result<(), MoveError> {
+            self.check(depot, step)?;
+            let mut remaining = step.count;
+            while remaining > 0 {
+                remaining -= self.lift(depot, step.from, step.to, remaining);
+            }
+            Ok(())
+        }
+    }
+
+    /// Replays moves one lift of the crane at a time.
+    struct Replay<'a> {
+        crane: Crane,
+        depot: Depot,
+        moves: &'a [Move],
+        next_move: usize,
+        /// The crates of the current move that haven't been moved yet.
+        remaining: usize,
+    }
+
+    /// A single lift of the crane, as part of the move with the given index.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    struct Lift {
+        move_index: usize,
+        count: usize,
+    }
+
+    impl<'a> Replay<'a> {
+        fn new(crane: Crane, depot: Depot, moves: &'a [Move]) -> Replay<'a> {
+            Replay {
+                crane,
+                depot,
+                moves,
+                next_move: 0,
+                remaining: 0,
+            }
+        }
+
+        fn step(&mut self) -> Option<Result<Lift, MoveError>> {
+            let step = self.moves.get(self.next_move)?;
+            if self.remaining == 0 {
+                if let Err(e) = self.crane.check(&self.depot, step) {
+                    return Some(Err(e));
                 }
-                Day5Mode::AllAtOnce => {
-                    let from = &mut depot.stacks[from];
-                    let mut drained = from.drain((from.len() - count)..).collect_vec();
-                    depot.stacks[to].append(&mut drained);
+                self.remaining = step.count;
+            }
+            let move_index = self.next_move;
+            let count = match self.remaining {
+                0 => 0,
+                remaining => self
+                    .crane
+                    .lift(&mut self.depot, step.from, step.to, remaining),
+            };
+            self.remaining -= count;
+            if self.remaining == 0 {
+                self.next_move += 1;
+            }
+            Some(Ok(Lift { move_index, count }))
+        }
+
+        /// Runs the remaining moves, returning the final depot.
+        fn finish(mut self) -> Result<Depot, MoveError> {
+            while let Some(lift) = self.step() {
+                lift?;
+            }
+            Ok(self.depot)
+        }
+    }
+
+    /// How far the planner may search before giving up.
+    #[derive(Clone, Copy, Debug)]
+    struct PlanLimits {
+        max_moves: usize,
+        max_states: usize,
+        /// The most crates a single move may take, if less than a whole stack.
+        max_count: Option<usize>,
+    }
+
+    impl Default for PlanLimits {
+        fn default() -> Self {
+            PlanLimits {
+                max_moves: 10,
+                max_states: 1_000_000,
+                max_count: None,
+            }
+        }
+    }
+
+    #[derive(Debug, PartialEq, Eq)]
+    enum PlanError {
+        DifferentStackCount,
+        /// The target doesn't have the same crates as the start.
+        DifferentCrates,
+        /// Every arrangement within the move limit was tried.
+        NoPlan {
+            max_moves: usize,
+        },
+        TooManyStates {
+            max_states: usize,
+        },
+    }
+
+    impl fmt::Display for PlanError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                PlanError::DifferentStackCount => write!(f, "the number of stacks differs"),
+                PlanError::DifferentCrates => write!(f, "the crates differ"),
+                PlanError::NoPlan { max_moves } => {
+                    write!(f, "no plan of at most {max_moves} moves")
+                }
+                PlanError::TooManyStates { max_states } => {
+                    write!(f, "gave up after {max_states} arrangements")
                 }
             }
         }
+    }
+
+    impl Error for PlanError {}
+
+    /// Finds a shortest list of moves that turns one depot into the other, with a breadth first
+    /// search over the arrangements the crane can make.
+    fn plan(
+        start: &Depot,
+        target: &Depot,
+        crane: Crane,
+        limits: PlanLimits,
+    ) -> Result<Vec<Move>, PlanError> {
+        if start.stacks.len() != target.stacks.len() {
+            Err(PlanError::DifferentStackCount)?
+        }
+        let crates = |depot: &Depot| {
+            depot
+                .stacks
+                .iter()
+                .flatten()
+                .copied()
+                .sorted()
+                .collect_vec()
+        };
+        if crates(start) != crates(target) {
+            Err(PlanError::DifferentCrates)?
+        }
+
+        // every arrangement that was reached, with the move that first reached it
+        let mut reached: HashMap<Depot, Option<(Depot, Move)>> =
+            HashMap::from([(start.clone(), None)]);
+        let mut frontier = VecDeque::from([(start.clone(), 0)]);
+        while let Some((depot, depth)) = frontier.pop_front() {
+            if &depot == target {
+                let mut moves = Vec::new();
+                let mut current = depot;
+                while let Some((previous, step)) = reached[&current].clone() {
+                    moves.push(step);
+                    current = previous;
+                }
+                moves.reverse();
+                return Ok(moves);
+            }
+            if depth == limits.max_moves {
+                continue;
+            }
+            let stack_count = depot.stacks.len();
+            for (from, to) in (0..stack_count).cartesian_product(0..stack_count) {
+                if from == to {
+                    continue;
+                }
+                let available = depot.stacks[from].len();
+                let max_count = limits.max_count.map_or(available, |max| max.min(available));
+                for count in 1..=max_count {
+                    let step = Move { count, from, to };
+                    let mut next = depot.clone();
+                    crane.apply(&mut next, &step).unwrap();
+                    if reached.contains_key(&next) {
+                        continue;
+                    }
+                    if reached.len() >= limits.max_states {
+                        Err(PlanError::TooManyStates {
+                            max_states: limits.max_states,
+                        })?
+                    }
+                    reached.insert(next.clone(), Some((depot.clone(), step)));
+                    frontier.push_back((next, depth + 1));
+                }
+            }
+        }
+        Err(PlanError::NoPlan {
+            max_moves: limits.max_moves,
+        })
+    }
+
+    /// A plan in the format of the puzzle input, checked by replaying it like any other input.
+    fn plan_input(
+        start: &Depot,
+        target: &Depot,
+        crane: Crane,
+        limits: PlanLimits,
+    ) -> Result<String, Box<dyn Error>> {
+        let moves = plan(start, target, crane, limits)?;
+        let input = format!("{start}\n\n{}", moves.iter().join("\n"));
+
+        let (depot, moves) = parse_input(&input)?;
+        let result = Replay::new(crane, depot, &moves).finish()?;
+        if &result != target {
+            Err(format!("The plan ends in\n{result}"))?
+        }
+        Ok(input)
+    }
+
+    fn day5(input: &str, crane: Crane) -> Result<String, Box<dyn std::error::Error>> {
+        let (depot, moves) = parse_input(input)?;
+        let depot = Replay::new(crane, depot, &moves).finish()?;
+        Ok(depot.top_crates())
+    }
+
+    #[test]
+    fn day5_sample() -> Result<(), Box<dyn std::error::Error>> {
+        let input = get_sample_input(5)?;
+        assert!(day5(&input, Crane::CrateMover9000)? == "CMZ");
+        assert!(day5(&input, Crane::CrateMover9001)? == "MCD");
+        assert!(day5(&input, Crane::AtMost(1))? == "CMZ");
+        assert!(day5(&input, Crane::AtMost(2))? == "MCZ");
+        assert!(day5(&input, Crane::AtMost(3))? == "MCD");
+
+        // the drawing is rendered just like it was read
+        let (depot, moves) = parse_input(&input)?;
+        let drawing = input.split_once("\n\n").unwrap().0;
+        assert!(depot.to_string() == drawing);
+        assert!(moves.iter().join("\n") == input.split_once("\n\n").unwrap().1);
 
-        let mut answer = String::new();
-        for mut stack in depot.stacks {
-            if !stack.is_empty() {
-                answer.push(stack.pop().unwrap());
+        // the second move takes three crates from the first stack, two at a time
+        let mut replay = Replay::new(Crane::AtMost(2), depot, &moves);
+        let mut lifts = Vec::new();
+        while let Some(lift) = replay.step() {
+            let lift = lift?;
+            lifts.push(lift);
+            if lifts.len() == 2 {
+                assert!(
+                    replay.depot.to_string()
+                        == "        [D]\n    [C] [N]\n[Z] [M] [P]\n 1   2   3 "
+                );
             }
         }
+        assert!(
+            lifts
+                == [(0, 1), (1, 2), (1, 1), (2, 2), (3, 1)]
+                    .map(|(move_index, count)| Lift { move_index, count })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn day5_drawings() -> Result<(), Box<dyn std::error::Error>> {
+        // more than nine stacks, and empty ones
+        let depot = Depot {
+            stacks: vec![
+                vec!['A'],
+                vec![],
+                vec!['B', 'C'],
+                vec![],
+                vec![],
+                vec![],
+                vec![],
+                vec![],
+                vec![],
+                vec!['D'],
+                vec!['E', 'F', 'G'],
+            ],
+        };
+        let drawing = depot.to_string();
+        assert!(drawing.lines().last() == Some(" 1   2   3   4   5   6   7   8   9   10  11"));
+        assert!(drawing.parse::<Depot>()? == depot);
+        assert!(depot.top_crates() == "ACDG");
+
+        let two_stacks = "[A]    \n[B] [C]\n 1   2 ";
+        let depot: Depot = two_stacks.parse()?;
+        assert!(depot.stacks == vec![vec!['B', 'A'], vec!['C']]);
+        assert!(depot.to_string() == two_stacks);
+
+        assert!("[A]\n 2 ".parse::<Depot>().is_err());
+        assert!("[A]    \n    [B]\n 1   2 ".parse::<Depot>().is_err());
+        assert!("[A] [B]\n 1 ".parse::<Depot>().is_err());
+
+        let mut depot: Depot = two_stacks.parse()?;
+        let moves = parse_moves("move 3 from 1 to 2\nmove 1 from 3 to 1")?;
+        assert!(
+            Crane::CrateMover9001.apply(&mut depot, &moves[0])
+                == Err(MoveError::NotEnoughCrates {
+                    stack: 0,
+                    available: 2
+                })
+        );
+        assert!(
+            Crane::CrateMover9001.apply(&mut depot, &moves[1]) == Err(MoveError::NoSuchStack(2))
+        );
+        assert!(parse_moves("move 1 from 0 to 1").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn day5_planner() -> Result<(), Box<dyn std::error::Error>> {
+        let input = get_sample_input(5)?;
+        let (start, moves) = parse_input(&input)?;
+        for crane in [
+            Crane::CrateMover9000,
+            Crane::CrateMover9001,
+            Crane::AtMost(2),
+        ] {
+            let target = Replay::new(crane, start.clone(), &moves).finish()?;
+            let planned = plan_input(&start, &target, crane, PlanLimits::default())?;
+            let (_, planned_moves) = parse_input(&planned)?;
+            assert!(planned_moves.len() <= moves.len(), "{crane:?}");
+            assert!(planned.starts_with(&start.to_string()));
+        }
+
+        // reversing two crates takes the old crane two moves, through the spare stack
+        let start: Depot = "[B]        \n[A]        \n 1   2   3 ".parse()?;
+        let target: Depot = "    [B]    \n    [A]    \n 1   2   3 ".parse()?;
+        let limits = PlanLimits::default();
+        assert!(
+            plan(&start, &target, Crane::CrateMover9001, limits)?
+                == vec![Move {
+                    count: 2,
+                    from: 0,
+                    to: 1
+                }]
+        );
+        assert!(plan(&start, &target, Crane::CrateMover9000, limits)?.len() == 2);
+        assert!(plan(&start, &start, Crane::CrateMover9000, limits)?.is_empty());
+
+        // without the spare stack, the old crane can only ever flip the pair over
+        let start: Depot = "[B]    \n[A]    \n 1   2 ".parse()?;
+        let target: Depot = "    [B]\n    [A]\n 1   2 ".parse()?;
+        assert!(
+            plan(&start, &target, Crane::CrateMover9000, limits)
+                == Err(PlanError::NoPlan { max_moves: 10 })
+        );
+        let one_at_a_time = PlanLimits {
+            max_count: Some(1),
+            ..limits
+        };
+        assert!(plan(&start, &target, Crane::CrateMover9001, one_at_a_time).is_err());
+        let few_states = PlanLimits {
+            max_states: 2,
+            ..limits
+        };
+        assert!(
+            plan(&start, &target, Crane::CrateMover9000, few_states)
+                == Err(PlanError::TooManyStates { max_states: 2 })
+        );
+
+        let other: Depot = "[C]    \n[A]    \n 1   2 ".parse()?;
+        assert!(
+            plan(&start, &other, Crane::CrateMover9000, limits) == Err(PlanError::DifferentCrates)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn day5_part1() -> Result<(), Box<dyn std::error::Error>> {
+        let answer = day5(&get_input(5)?, Crane::CrateMover9000)?;
+        println!("Answer: {answer}");
+        Ok(())
+    }
+
+    #[test]
+    fn day5_part2() -> Result<(), Box<dyn std::error::Error>> {
+        let answer = day5(&get_input(5)?, Crane::CrateMover9001)?;
         println!("Answer: {answer}");
         Ok(())
     }
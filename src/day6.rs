@@ -1,8 +1,9 @@
 #[cfg(test)]
 mod tests {
 
-    use crate::common::tests::get_input;
+    use crate::common::tests::{get_input, pseudo_random};
     use std::collections::HashSet;
+    use std::io::{self, Read};
 
     #[test]
     fn day6_part1() -> Result<(), Box<dyn std::error::Error>> {
@@ -14,31 +15,177 @@ mod tests {
         day6(14)
     }
 
-    fn day6(num_chars: usize) -> Result<(), Box<dyn std::error::Error>> {
-        let input = get_input(6)?;
-        let mut chars = Vec::with_capacity(num_chars);
-        for (index, char) in input.chars().enumerate() {
-            if chars.len() < num_chars {
-                chars.push(char);
-                continue;
+    /// The last `size` bytes of a stream, with a count per byte value so the number of distinct
+    /// bytes in the window is updated in O(1) for every byte that comes in.
+    struct Window {
+        recent: Vec<u8>,
+        next: usize,
+        filled: usize,
+        counts: [u32; 256],
+        distinct: usize,
+    }
+
+    impl Window {
+        /// A window of the given size, if it isn't empty.
+        fn new(size: usize) -> Option<Window> {
+            (size > 0).then(|| Window {
+                recent: vec![0; size],
+                next: 0,
+                filled: 0,
+                counts: [0; 256],
+                distinct: 0,
+            })
+        }
+
+        /// Adds a byte, dropping the oldest one once the window is full.
+        /// Returns whether all bytes in the (full) window now differ.
+        fn push(&mut self, byte: u8) -> bool {
+            let size = self.recent.len();
+            if self.filled == size {
+                let outgoing = self.recent[self.next] as usize;
+                self.counts[outgoing] -= 1;
+                if self.counts[outgoing] == 0 {
+                    self.distinct -= 1;
+                }
+            } else {
+                self.filled += 1;
+            }
+            self.recent[self.next] = byte;
+            self.next = (self.next + 1) % size;
+            self.counts[byte as usize] += 1;
+            if self.counts[byte as usize] == 1 {
+                self.distinct += 1;
             }
+            self.distinct == size
+        }
+    }
 
-            chars.remove(0);
-            chars.push(char);
+    /// Scans a stream once for markers of several sizes, calling `on_marker` with the index of the size and
+    /// the number of bytes read so far every time the last `size` bytes are all different.
+    /// Returns the number of bytes read, or an `InvalidInput` error for a size of 0.
+    fn scan_markers<R: Read>(
+        mut reader: R,
+        sizes: &[usize],
+        mut on_marker: impl FnMut(usize, u64),
+    ) -> io::Result<u64> {
+        let mut windows = sizes
+            .iter()
+            .map(|&size| Window::new(size))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "An empty window is always distinct",
+                )
+            })?;
+        let mut buffer = [0; 64 * 1024];
+        let mut position = 0u64;
+        loop {
+            let read = match reader.read(&mut buffer) {
+                Ok(0) => return Ok(position),
+                Ok(read) => read,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            for &byte in &buffer[..read] {
+                position += 1;
+                for (index, window) in windows.iter_mut().enumerate() {
+                    if window.push(byte) {
+                        on_marker(index, position);
+                    }
+                }
+            }
+        }
+    }
 
-            assert!(chars.len() == num_chars);
+    /// Every marker position, per size.
+    fn all_markers<R: Read>(reader: R, sizes: &[usize]) -> io::Result<Vec<Vec<u64>>> {
+        let mut markers = vec![Vec::new(); sizes.len()];
+        scan_markers(reader, sizes, |index, position| {
+            markers[index].push(position);
+        })?;
+        Ok(markers)
+    }
 
-            let unique_chars: HashSet<&char> = chars.iter().collect();
+    /// The first marker position, per size.
+    fn first_markers<R: Read>(reader: R, sizes: &[usize]) -> io::Result<Vec<Option<u64>>> {
+        let mut markers = vec![None; sizes.len()];
+        scan_markers(reader, sizes, |index, position| {
+            markers[index].get_or_insert(position);
+        })?;
+        Ok(markers)
+    }
 
-            if unique_chars.len() == num_chars {
-                println!("Set: {:?}", unique_chars);
-                println!("Number of characters to receive: {}", index + 1);
-                return Ok(());
-            }
+    #[test]
+    fn day6_samples() -> Result<(), Box<dyn std::error::Error>> {
+        let samples = [
+            ("mjqjpqmgbljsphdztnvjfqwrcgsmlb", 7, 19),
+            ("bvwbjplbgvbhsrlpgdmjqwftvncz", 5, 23),
+            ("nppdvjthqldpwncqszvftbrmjlhg", 6, 23),
+            ("nznrnfrfntjfmvfwmzdfjlvtqnbhcprsgzwm", 10, 29),
+            ("zcfzfwzzqfrljwzlrfnpqdbhtmscgvjw", 11, 26),
+        ];
+        for (input, packet, message) in samples {
+            let markers = first_markers(input.as_bytes(), &[4, 14])?;
+            assert!(markers == vec![Some(packet), Some(message)], "{input}");
+        }
+
+        assert!(all_markers("aabcdd".as_bytes(), &[4])? == vec![vec![5]]);
+        assert!(all_markers("abcde".as_bytes(), &[4, 5, 6])? == vec![vec![4, 5], vec![5], vec![]]);
+        assert!(first_markers("abcd".as_bytes(), &[4, 2, 4])? == vec![Some(4), Some(2), Some(4)]);
+        let error = all_markers("abcd".as_bytes(), &[4, 0]).unwrap_err();
+        assert!(error.kind() == io::ErrorKind::InvalidInput);
+
+        Ok(())
+    }
+
+    #[test]
+    fn day6_matches_naive_windows() -> Result<(), Box<dyn std::error::Error>> {
+        let text: Vec<u8> = pseudo_random(6)
+            .take(5000)
+            .map(|random| b'a' + (random % 16) as u8)
+            .collect();
+
+        let sizes = [1, 4, 7, 14];
+        let markers = all_markers(text.as_slice(), &sizes)?;
+        for (size, markers) in sizes.iter().zip(markers) {
+            let naive: Vec<u64> = text
+                .windows(*size)
+                .enumerate()
+                .filter(|(_, window)| window.iter().collect::<HashSet<_>>().len() == *size)
+                .map(|(start, _)| (start + size) as u64)
+                .collect();
+            assert!(markers == naive, "{size}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn day6_long_stream() -> Result<(), Box<dyn std::error::Error>> {
+        // 8 MiB of the same byte with the only markers at the very end, never held in memory
+        let length = 8 * 1024 * 1024;
+        let stream = io::repeat(b'x').take(length).chain("abcd".as_bytes());
+        let mut markers = Vec::new();
+        let read = scan_markers(stream, &[4, 14], |index, position| {
+            markers.push((index, position))
+        })?;
+        assert!(read == length + 4);
+        assert!(markers == vec![(0, length + 3), (0, length + 4)]);
+
+        Ok(())
+    }
+
+    fn day6(num_chars: usize) -> Result<(), Box<dyn std::error::Error>> {
+        let input = get_input(6)?;
+        let markers = first_markers(input.as_bytes(), &[num_chars])?;
+        match markers[0] {
+            Some(position) => println!("Number of characters to receive: {position}"),
+            None => panic!(
+                "Didn't find a sequence of {} subsequent different characters",
+                num_chars
+            ),
         }
-        panic!(
-            "Didn't find a sequence of {} subsequent different characters",
-            num_chars
-        );
+        Ok(())
     }
 }
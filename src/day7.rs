@@ -1,75 +1,691 @@
 #[cfg(test)]
 mod tests {
+    use std::{
+        collections::BTreeMap,
+        error::Error,
+        fmt,
+        fmt::Write,
+        fs, io,
+        path::{Path, PathBuf},
+        process::Command,
+    };
 
-    use crate::common::tests::get_input;
+    use crate::common::tests::{get_input, get_sample_input};
 
-    #[test]
-    fn day7() -> Result<(), Box<dyn std::error::Error>> {
-        let input = get_input(7)?;
+    const DISK_SIZE: usize = 70_000_000;
+    const SPACE_NEEDED: usize = 30_000_000;
 
-        fn traverse<F>(input: &str, mut finish_dir: F) -> Result<usize, Box<dyn std::error::Error>>
-        where
-            F: FnMut(usize),
-        {
-            let mut sizes_stack: Vec<usize> = Vec::new();
-            let mut total_size = None;
-            for line in input.lines() {
-                if line.eq("$ cd ..") {
-                    let dir_size = sizes_stack.pop().unwrap();
-                    finish_dir(dir_size);
-                    *(sizes_stack.last_mut().unwrap()) += dir_size; //add the size of a child dir to the current dir
-                } else if line.starts_with("$ cd /") {
-                    assert!(sizes_stack.is_empty());
-                    sizes_stack.push(0);
-                } else if line.starts_with("$ cd ") {
-                    sizes_stack.push(0);
-                } else if line.starts_with("$ ls") {
-                    //ignore
-                } else if line.starts_with("dir ") {
-                    //ignore, we'll get there later
-                } else {
-                    // line with a size and filename
-                    let space = line.find(' ').unwrap();
-                    let file_size: usize = (&line[..space]).parse()?;
-                    *(sizes_stack.last_mut().unwrap()) += file_size;
+    const ROOT: usize = 0;
+
+    #[derive(Debug)]
+    enum NodeKind {
+        /// The entries by name, and whether an `ls` has shown them all.
+        Directory {
+            entries: BTreeMap<String, usize>,
+            listed: bool,
+        },
+        File(usize),
+    }
+
+    #[derive(Debug)]
+    struct Node {
+        name: String,
+        parent: Option<usize>,
+        kind: NodeKind,
+    }
+
+    /// The entries of an `ls`, with the size of the files and the line they're on.
+    type Listing = BTreeMap<String, (Option<usize>, usize)>;
+
+    #[derive(Debug, PartialEq, Eq)]
+    enum LogError {
+        UnknownCommand {
+            line: usize,
+        },
+        /// Output that doesn't follow an `ls`.
+        UnexpectedOutput {
+            line: usize,
+        },
+        InvalidEntry {
+            line: usize,
+        },
+        /// A `cd ..` in the root directory.
+        NoParent {
+            line: usize,
+        },
+        NotADirectory {
+            line: usize,
+            path: String,
+        },
+        /// A `cd` into a directory the earlier listing of its parent didn't show.
+        UnknownDirectory {
+            line: usize,
+            path: String,
+        },
+        /// An entry that doesn't match what an earlier `ls` showed.
+        InconsistentListing {
+            line: usize,
+            name: String,
+        },
+    }
+
+    impl fmt::Display for LogError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                LogError::UnknownCommand { line } => write!(f, "line {line}: unknown command"),
+                LogError::UnexpectedOutput { line } => {
+                    write!(f, "line {line}: output without an ls")
+                }
+                LogError::InvalidEntry { line } => write!(f, "line {line}: invalid ls entry"),
+                LogError::NoParent { line } => {
+                    write!(f, "line {line}: the root directory has no parent")
                 }
+                LogError::NotADirectory { line, path } => {
+                    write!(f, "line {line}: {path} is not a directory")
+                }
+                LogError::UnknownDirectory { line, path } => {
+                    write!(f, "line {line}: {path} was not listed")
+                }
+                LogError::InconsistentListing { line, name } => {
+                    write!(f, "line {line}: {name} doesn't match an earlier listing")
+                }
+            }
+        }
+    }
+
+    impl Error for LogError {}
+
+    /// An in-memory filesystem, as far as the terminal log shows it.
+    ///
+    /// Nodes are kept in a single list and refer to each other by index, the root being the
+    /// first one.
+    #[derive(Debug)]
+    struct FileSystem {
+        nodes: Vec<Node>,
+    }
+
+    impl FileSystem {
+        fn new() -> FileSystem {
+            FileSystem {
+                nodes: vec![Node {
+                    name: "/".to_string(),
+                    parent: None,
+                    kind: NodeKind::Directory {
+                        entries: BTreeMap::new(),
+                        listed: false,
+                    },
+                }],
             }
+        }
 
-            while let Some(dir_size) = sizes_stack.pop() {
-                finish_dir(dir_size);
-                if let Some(r) = sizes_stack.last_mut() {
-                    *r += dir_size;
+        /// Replays the terminal log.
+        ///
+        /// `cd` accepts `/`, `..` and paths relative to the current directory or to the root.
+        /// Running `ls` on a directory that was listed before must show the same entries.
+        fn from_log(log: &str) -> Result<FileSystem, LogError> {
+            let mut fs = FileSystem::new();
+            let mut current = ROOT;
+            // the entries shown by the `ls` that is being read, if any, with their line numbers
+            let mut listing: Option<Listing> = None;
+
+            for (index, line) in log.lines().enumerate() {
+                let line_number = index + 1;
+                if line.starts_with('$') {
+                    if let Some(entries) = listing.take() {
+                        fs.apply_listing(current, entries, line_number - 1)?;
+                    }
+                }
+                if let Some(path) = line.strip_prefix("$ cd ") {
+                    current = fs.change_directory(current, path.trim(), line_number)?;
+                } else if line.trim() == "$ ls" {
+                    listing = Some(BTreeMap::new());
+                } else if line.starts_with('$') {
+                    Err(LogError::UnknownCommand { line: line_number })?
                 } else {
-                    println!("Total size: {dir_size}");
-                    total_size = Some(dir_size);
+                    let entries = listing
+                        .as_mut()
+                        .ok_or(LogError::UnexpectedOutput { line: line_number })?;
+                    let (size, name) = line
+                        .split_once(' ')
+                        .filter(|(_, name)| {
+                            !name.is_empty() && !name.contains('/') && !matches!(*name, "." | "..")
+                        })
+                        .ok_or(LogError::InvalidEntry { line: line_number })?;
+                    let size = match size {
+                        "dir" => None,
+                        size => Some(
+                            size.parse()
+                                .map_err(|_| LogError::InvalidEntry { line: line_number })?,
+                        ),
+                    };
+                    if entries
+                        .insert(name.to_string(), (size, line_number))
+                        .is_some()
+                    {
+                        Err(LogError::InconsistentListing {
+                            line: line_number,
+                            name: name.to_string(),
+                        })?
+                    }
+                }
+            }
+            if let Some(entries) = listing {
+                fs.apply_listing(current, entries, log.lines().count())?;
+            }
+            Ok(fs)
+        }
+
+        fn entries(&self, directory: usize) -> Option<&BTreeMap<String, usize>> {
+            match &self.nodes[directory].kind {
+                NodeKind::Directory { entries, .. } => Some(entries),
+                NodeKind::File(_) => None,
+            }
+        }
+
+        fn add(&mut self, parent: usize, name: &str, kind: NodeKind) -> usize {
+            let index = self.nodes.len();
+            self.nodes.push(Node {
+                name: name.to_string(),
+                parent: Some(parent),
+                kind,
+            });
+            if let NodeKind::Directory { entries, .. } = &mut self.nodes[parent].kind {
+                entries.insert(name.to_string(), index);
+            }
+            index
+        }
+
+        fn change_directory(
+            &mut self,
+            mut current: usize,
+            path: &str,
+            line: usize,
+        ) -> Result<usize, LogError> {
+            if path.starts_with('/') {
+                current = ROOT;
+            }
+            for component in path.split('/').filter(|c| !c.is_empty() && *c != ".") {
+                if component == ".." {
+                    current = self.nodes[current]
+                        .parent
+                        .ok_or(LogError::NoParent { line })?;
+                    continue;
                 }
+                let (entry, listed) = match &self.nodes[current].kind {
+                    NodeKind::Directory { entries, listed } => {
+                        (entries.get(component).copied(), *listed)
+                    }
+                    NodeKind::File(_) => unreachable!("only directories are entered"),
+                };
+                current = match entry {
+                    Some(entry) if self.entries(entry).is_some() => entry,
+                    Some(entry) => Err(LogError::NotADirectory {
+                        line,
+                        path: self.path(entry),
+                    })?,
+                    // without a listing, the log doesn't tell whether the directory exists
+                    None if !listed => self.add(
+                        current,
+                        component,
+                        NodeKind::Directory {
+                            entries: BTreeMap::new(),
+                            listed: false,
+                        },
+                    ),
+                    None => Err(LogError::UnknownDirectory {
+                        line,
+                        path: format!("{}{component}", self.directory_prefix(current)),
+                    })?,
+                };
             }
-            Ok(total_size.unwrap())
+            Ok(current)
         }
 
-        let total_size = {
-            let max_size = 100_000;
-            let mut sum = 0;
-            let total_size = traverse(&input, |dir_size| {
-                if dir_size <= max_size {
-                    sum += dir_size;
+        /// Adds the entries an `ls` showed, checking them against what is known already.
+        fn apply_listing(
+            &mut self,
+            directory: usize,
+            listing: Listing,
+            last_line: usize,
+        ) -> Result<(), LogError> {
+            let (entries, listed) = match &self.nodes[directory].kind {
+                NodeKind::Directory { entries, listed } => (entries.clone(), *listed),
+                NodeKind::File(_) => unreachable!("only directories are listed"),
+            };
+            if listed {
+                if let Some(name) = entries.keys().find(|name| !listing.contains_key(*name)) {
+                    Err(LogError::InconsistentListing {
+                        line: last_line,
+                        name: name.clone(),
+                    })?
+                }
+            }
+            for (name, (size, line)) in listing {
+                let kind = match size {
+                    Some(size) => NodeKind::File(size),
+                    None => NodeKind::Directory {
+                        entries: BTreeMap::new(),
+                        listed: false,
+                    },
+                };
+                match entries.get(&name).map(|&entry| &self.nodes[entry].kind) {
+                    None if listed => Err(LogError::InconsistentListing { line, name })?,
+                    None => {
+                        self.add(directory, &name, kind);
+                    }
+                    Some(NodeKind::File(existing)) if size == Some(*existing) => {}
+                    Some(NodeKind::Directory { .. }) if size.is_none() => {}
+                    Some(_) => Err(LogError::InconsistentListing { line, name })?,
                 }
-            })?;
+            }
+            if let NodeKind::Directory { listed, .. } = &mut self.nodes[directory].kind {
+                *listed = true;
+            }
+            Ok(())
+        }
 
-            println!("sum of filtered dirs: {sum}");
-            total_size
-        };
+        /// The node at an absolute path.
+        fn find(&self, path: &str) -> Option<usize> {
+            let mut current = ROOT;
+            for component in path.split('/').filter(|c| !c.is_empty()) {
+                current = match component {
+                    "." => current,
+                    ".." => self.nodes[current].parent?,
+                    name => *self.entries(current)?.get(name)?,
+                };
+            }
+            Some(current)
+        }
 
-        {
-            let space_to_free_up = 30_000_000 - (70_000_000 - total_size);
-            let mut size_of_dir_to_delete = usize::MAX;
+        fn path(&self, node: usize) -> String {
+            match self.nodes[node].parent {
+                None => "/".to_string(),
+                Some(parent) => {
+                    format!("{}{}", self.directory_prefix(parent), self.nodes[node].name)
+                }
+            }
+        }
+
+        fn directory_prefix(&self, directory: usize) -> String {
+            match directory {
+                ROOT => "/".to_string(),
+                _ => format!("{}/", self.path(directory)),
+            }
+        }
 
-            traverse(&input, |dir_size| {
-                if dir_size >= space_to_free_up && dir_size < size_of_dir_to_delete {
-                    size_of_dir_to_delete = dir_size;
+        /// The total size of every node, with the sizes of directories including everything in them.
+        fn sizes(&self) -> Vec<usize> {
+            let mut sizes = vec![0; self.nodes.len()];
+            // children are always added after their parent
+            for (index, node) in self.nodes.iter().enumerate().rev() {
+                if let NodeKind::File(size) = node.kind {
+                    sizes[index] = size;
                 }
-            })?;
-            println!("size of dir to delete: {size_of_dir_to_delete}");
+                if let Some(parent) = node.parent {
+                    sizes[parent] += sizes[index];
+                }
+            }
+            sizes
+        }
+
+        fn directories(&self) -> impl Iterator<Item = usize> + '_ {
+            (0..self.nodes.len()).filter(|&node| self.entries(node).is_some())
+        }
+
+        /// Like `du`: the size of every directory at or below the path, each directory after
+        /// its subdirectories.
+        fn du(&self, path: &str) -> Option<Vec<(String, usize)>> {
+            let sizes = self.sizes();
+            let mut result = Vec::new();
+            self.du_into(self.find(path)?, &sizes, &mut result);
+            Some(result)
+        }
+
+        fn du_into(&self, node: usize, sizes: &[usize], result: &mut Vec<(String, usize)>) {
+            if let Some(entries) = self.entries(node) {
+                for &entry in entries.values() {
+                    self.du_into(entry, sizes, result);
+                }
+                result.push((self.path(node), sizes[node]));
+            }
+        }
+
+        /// The tree as the puzzle description draws it.
+        fn tree(&self) -> String {
+            let mut tree = String::new();
+            self.tree_into(ROOT, 0, &mut tree);
+            tree
+        }
+
+        fn tree_into(&self, node: usize, depth: usize, tree: &mut String) {
+            let indent = "  ".repeat(depth);
+            let name = &self.nodes[node].name;
+            match &self.nodes[node].kind {
+                NodeKind::Directory { entries, .. } => {
+                    writeln!(tree, "{indent}- {name} (dir)").unwrap();
+                    for &entry in entries.values() {
+                        self.tree_into(entry, depth + 1, tree);
+                    }
+                }
+                NodeKind::File(size) => {
+                    writeln!(tree, "{indent}- {name} (file, size={size})").unwrap()
+                }
+            }
+        }
+
+        /// The smallest directory that, once deleted, leaves `needed` free on a disk of the given size.
+        fn smallest_directory_freeing(
+            &self,
+            disk_size: usize,
+            needed: usize,
+        ) -> Option<(String, usize)> {
+            let sizes = self.sizes();
+            let to_free = needed.saturating_sub(disk_size.checked_sub(sizes[ROOT])?);
+            self.directories()
+                .filter(|&directory| sizes[directory] >= to_free)
+                .min_by_key(|&directory| sizes[directory])
+                .map(|directory| (self.path(directory), sizes[directory]))
+        }
+
+        /// Creates the tree below `target`, with sparse files of the logged sizes.
+        fn materialize(&self, target: &Path) -> io::Result<()> {
+            self.materialize_into(ROOT, target)
+        }
+
+        fn materialize_into(&self, directory: usize, target: &Path) -> io::Result<()> {
+            fs::create_dir_all(target)?;
+            for (name, &entry) in self.entries(directory).into_iter().flatten() {
+                let path = target.join(name);
+                match self.nodes[entry].kind {
+                    NodeKind::Directory { .. } => self.materialize_into(entry, &path)?,
+                    NodeKind::File(size) => fs::File::create(&path)?.set_len(size as u64)?,
+                }
+            }
+            Ok(())
+        }
+
+        fn sum_of_small_directories(&self, max_size: usize) -> usize {
+            let sizes = self.sizes();
+            self.directories()
+                .map(|directory| sizes[directory])
+                .filter(|&size| size <= max_size)
+                .sum()
+        }
+    }
+
+    /// A terminal session exploring an existing directory, in the format of the puzzle.
+    /// Anything that isn't a regular file or a directory, like a symbolic link, is left out.
+    fn session_for(root: &Path) -> io::Result<String> {
+        let mut session = String::from("$ cd /\n");
+        session_into(root, &mut session)?;
+        Ok(session)
+    }
+
+    fn session_into(directory: &Path, session: &mut String) -> io::Result<()> {
+        let mut files = Vec::new();
+        let mut directories = Vec::new();
+        for entry in fs::read_dir(directory)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let metadata = entry.path().symlink_metadata()?;
+            if metadata.is_dir() {
+                directories.push(name);
+            } else if metadata.is_file() {
+                files.push((name, metadata.len()));
+            }
+        }
+        directories.sort();
+        files.sort();
+
+        session.push_str("$ ls\n");
+        for name in &directories {
+            writeln!(session, "dir {name}").unwrap();
+        }
+        for (name, size) in &files {
+            writeln!(session, "{size} {name}").unwrap();
+        }
+        for name in &directories {
+            writeln!(session, "$ cd {name}").unwrap();
+            session_into(&directory.join(name), session)?;
+            session.push_str("$ cd ..\n");
+        }
+        Ok(())
+    }
+
+    /// A fresh directory to work in, removed again when dropped.
+    struct ScratchDirectory(PathBuf);
+
+    impl ScratchDirectory {
+        fn new(name: &str) -> io::Result<ScratchDirectory> {
+            let path = std::env::temp_dir().join(format!("{name}-{}", std::process::id()));
+            if path.exists() {
+                fs::remove_dir_all(&path)?;
+            }
+            fs::create_dir(&path)?;
+            Ok(ScratchDirectory(path))
+        }
+    }
+
+    impl Drop for ScratchDirectory {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Checks the sizes of the filesystem against `du --apparent-size` on its materialized copy.
+    /// Returns `false` without checking when there is no `du` that knows `--apparent-size`, like
+    /// on macOS.
+    ///
+    /// `du` also counts the size of the directories themselves, so those are added to the sizes
+    /// of the model before comparing.
+    fn check_against_du(fs: &FileSystem, root: &Path) -> Result<bool, Box<dyn Error>> {
+        let output = match Command::new("du")
+            .args(["--apparent-size", "--block-size=1"])
+            .arg(root)
+            .output()
+        {
+            Ok(output) if output.status.success() => output,
+            _ => return Ok(false),
+        };
+        let output = String::from_utf8(output.stdout)?;
+        let measured = output
+            .lines()
+            .map(|line| {
+                let (size, path) = line
+                    .split_once('\t')
+                    .ok_or_else(|| format!("Unexpected du output: {line}"))?;
+                Ok((PathBuf::from(path), size.parse()?))
+            })
+            .collect::<Result<BTreeMap<PathBuf, u64>, Box<dyn Error>>>()?;
+
+        let expected = fs.du("/").unwrap();
+        assert!(measured.len() == expected.len());
+        for (path, size) in expected {
+            let on_disk = root.join(path.trim_start_matches('/'));
+            let on_disk = on_disk.components().collect::<PathBuf>();
+            let overhead = directory_overhead(&on_disk)?;
+            assert!(
+                measured[&on_disk] == size as u64 + overhead,
+                "{path}: du says {}, expected {size} + {overhead}",
+                measured[&on_disk]
+            );
+        }
+        Ok(true)
+    }
+
+    /// The sizes of a directory and all directories in it.
+    fn directory_overhead(directory: &Path) -> io::Result<u64> {
+        let mut overhead = fs::symlink_metadata(directory)?.len();
+        for entry in fs::read_dir(directory)? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                overhead += directory_overhead(&entry.path())?;
+            }
+        }
+        Ok(overhead)
+    }
+
+    #[test]
+    fn day7_sample() -> Result<(), Box<dyn Error>> {
+        let fs = FileSystem::from_log(&get_sample_input(7)?)?;
+
+        assert!(fs.sum_of_small_directories(100_000) == 95437);
+        assert!(
+            fs.smallest_directory_freeing(DISK_SIZE, SPACE_NEEDED)
+                == Some(("/d".to_string(), 24933642))
+        );
+
+        assert!(
+            fs.du("/")
+                == Some(vec![
+                    ("/a/e".to_string(), 584),
+                    ("/a".to_string(), 94853),
+                    ("/d".to_string(), 24933642),
+                    ("/".to_string(), 48381165),
+                ])
+        );
+        assert!(fs.du("/a/e/../e") == Some(vec![("/a/e".to_string(), 584)]));
+        assert!(fs.du("/b.txt") == Some(vec![]));
+        assert!(fs.du("/x").is_none());
+
+        assert!(
+            fs.tree()
+                == "- / (dir)
+  - a (dir)
+    - e (dir)
+      - i (file, size=584)
+    - f (file, size=29116)
+    - g (file, size=2557)
+    - h.lst (file, size=62596)
+  - b.txt (file, size=14848514)
+  - c.dat (file, size=8504156)
+  - d (dir)
+    - d.ext (file, size=5626152)
+    - d.log (file, size=8033020)
+    - j (file, size=4060174)
+    - k (file, size=7214296)
+"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn day7_log_replay() -> Result<(), Box<dyn Error>> {
+        let sample = get_sample_input(7)?;
+
+        // jumping back to the root, listing twice and absolute paths all lead to the same tree
+        let wandering = "$ cd /\n$ ls\ndir a\n14848514 b.txt\n8504156 c.dat\ndir d\n\
+            $ cd /a\n$ ls\ndir e\n29116 f\n2557 g\n62596 h.lst\n\
+            $ cd /\n$ ls\ndir a\n14848514 b.txt\n8504156 c.dat\ndir d\n\
+            $ cd d\n$ ls\n4060174 j\n8033020 d.log\n5626152 d.ext\n7214296 k\n\
+            $ cd ../a/e\n$ ls\n584 i\n$ ls\n584 i";
+        let fs = FileSystem::from_log(wandering)?;
+        assert!(fs.tree() == FileSystem::from_log(&sample)?.tree());
+
+        let errors = [
+            ("$ cd /\n$ cd ..", LogError::NoParent { line: 2 }),
+            ("$ cd /\n$ rm -rf", LogError::UnknownCommand { line: 2 }),
+            ("$ cd /\n123 a", LogError::UnexpectedOutput { line: 2 }),
+            ("$ ls\n12x a", LogError::InvalidEntry { line: 2 }),
+            ("$ ls\ndir ..", LogError::InvalidEntry { line: 2 }),
+            ("$ ls\n12 .", LogError::InvalidEntry { line: 2 }),
+            (
+                "$ ls\n12 a\n$ cd a",
+                LogError::NotADirectory {
+                    line: 3,
+                    path: "/a".to_string(),
+                },
+            ),
+            (
+                "$ ls\ndir a\n$ cd b",
+                LogError::UnknownDirectory {
+                    line: 3,
+                    path: "/b".to_string(),
+                },
+            ),
+            (
+                "$ ls\n12 a\n$ ls\n13 a",
+                LogError::InconsistentListing {
+                    line: 4,
+                    name: "a".to_string(),
+                },
+            ),
+            (
+                "$ ls\n12 a\ndir b\n$ ls\ndir b\n$ cd b",
+                LogError::InconsistentListing {
+                    line: 5,
+                    name: "a".to_string(),
+                },
+            ),
+        ];
+        for (log, error) in errors {
+            assert!(FileSystem::from_log(log).unwrap_err() == error, "{log}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn day7_materialize() -> Result<(), Box<dyn Error>> {
+        let fs = FileSystem::from_log(&get_sample_input(7)?)?;
+        let scratch = ScratchDirectory::new("day7-sample")?;
+        fs.materialize(&scratch.0)?;
+
+        let metadata = fs::metadata(scratch.0.join("d").join("d.log"))?;
+        assert!(metadata.len() == 8033020);
+        if !check_against_du(&fs, &scratch.0)? {
+            println!("no du --apparent-size to check against");
+        }
+
+        // walking the copy gives a session that leads back to the same tree
+        let session = session_for(&scratch.0)?;
+        assert!(session.starts_with("$ cd /\n$ ls\ndir a\ndir d\n14848514 b.txt\n"));
+        assert!(FileSystem::from_log(&session)?.tree() == fs.tree());
+
+        // and so does a directory that wasn't made from a log
+        let scratch = ScratchDirectory::new("day7-handmade")?;
+        fs::create_dir_all(scratch.0.join("logs").join("old"))?;
+        fs::create_dir(scratch.0.join("empty"))?;
+        fs::write(scratch.0.join("notes.txt"), "remember the milk")?;
+        fs::write(scratch.0.join("logs").join("today.log"), [0; 1000])?;
+        fs::write(scratch.0.join("logs").join("old").join("1.log"), [0; 250])?;
+        let fs = FileSystem::from_log(&session_for(&scratch.0)?)?;
+        assert!(
+            fs.du("/")
+                == Some(vec![
+                    ("/empty".to_string(), 0),
+                    ("/logs/old".to_string(), 250),
+                    ("/logs".to_string(), 1250),
+                    ("/".to_string(), 1267),
+                ])
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn day7() -> Result<(), Box<dyn std::error::Error>> {
+        let input = get_input(7)?;
+        let fs = FileSystem::from_log(&input)?;
+
+        println!("Total size: {}", fs.sizes()[ROOT]);
+        println!(
+            "sum of filtered dirs: {}",
+            fs.sum_of_small_directories(100_000)
+        );
+        if let Some((path, size)) = fs.smallest_directory_freeing(DISK_SIZE, SPACE_NEEDED) {
+            println!("size of dir to delete: {size} ({path})");
+        }
+
+        let scratch = ScratchDirectory::new("day7")?;
+        fs.materialize(&scratch.0)?;
+        if check_against_du(&fs, &scratch.0)? {
+            println!(
+                "du --apparent-size agrees on {} directories",
+                fs.directories().count()
+            );
+        } else {
+            println!("no du --apparent-size to check against");
         }
 
         Ok(())
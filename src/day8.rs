@@ -1,29 +1,431 @@
 #[cfg(test)]
 mod tests {
 
-    use crate::common::tests::get_input;
-    use std::collections::HashSet;
+    use crate::common::tests::{get_input, get_sample_input, pseudo_random};
+    use std::{collections::HashSet, error::Error, fmt::Write};
 
+    use itertools::Itertools;
     use transpose::transpose;
 
+    /// The direction a tree is seen from, or looked towards.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    enum Direction {
+        North,
+        East,
+        South,
+        West,
+    }
+
+    const DIRECTIONS: [Direction; 4] = [
+        Direction::North,
+        Direction::East,
+        Direction::South,
+        Direction::West,
+    ];
+
+    /// A rectangular grid, stored row by row.
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    struct Grid<T> {
+        width: usize,
+        height: usize,
+        cells: Vec<T>,
+    }
+
+    impl<T> Grid<T> {
+        fn from_fn(width: usize, height: usize, mut f: impl FnMut(usize, usize) -> T) -> Grid<T> {
+            let cells = (0..height)
+                .flat_map(|y| (0..width).map(move |x| (x, y)))
+                .map(|(x, y)| f(x, y))
+                .collect();
+            Grid {
+                width,
+                height,
+                cells,
+            }
+        }
+
+        fn get(&self, x: usize, y: usize) -> &T {
+            &self.cells[y * self.width + x]
+        }
+
+        fn get_mut(&mut self, x: usize, y: usize) -> &mut T {
+            &mut self.cells[y * self.width + x]
+        }
+
+        fn map<U>(&self, f: impl FnMut(&T) -> U) -> Grid<U> {
+            Grid {
+                width: self.width,
+                height: self.height,
+                cells: self.cells.iter().map(f).collect(),
+            }
+        }
+
+        fn coords(&self) -> impl Iterator<Item = (usize, usize)> {
+            let width = self.width;
+            (0..self.height).flat_map(move |y| (0..width).map(move |x| (x, y)))
+        }
+
+        /// Every row or column as it is met walking away from the edge on the given side.
+        fn lines_from(&self, edge: Direction) -> Vec<Vec<(usize, usize)>> {
+            let (width, height) = (self.width, self.height);
+            match edge {
+                Direction::North => (0..width)
+                    .map(|x| (0..height).map(|y| (x, y)).collect())
+                    .collect(),
+                Direction::South => (0..width)
+                    .map(|x| (0..height).rev().map(|y| (x, y)).collect())
+                    .collect(),
+                Direction::West => (0..height)
+                    .map(|y| (0..width).map(|x| (x, y)).collect())
+                    .collect(),
+                Direction::East => (0..height)
+                    .map(|y| (0..width).rev().map(|x| (x, y)).collect())
+                    .collect(),
+            }
+        }
+    }
+
+    fn parse(input: &str) -> Result<Grid<u8>, Box<dyn Error>> {
+        let lines = input.lines().collect_vec();
+        let width = lines.first().map_or(0, |line| line.len());
+        let mut cells = Vec::with_capacity(width * lines.len());
+        for line in &lines {
+            if line.len() != width {
+                Err(format!("Not a rectangular forest: {line}"))?
+            }
+            for c in line.chars() {
+                cells.push(c.to_digit(10).ok_or_else(|| format!("Not a height: {c}"))? as u8);
+            }
+        }
+        Ok(Grid {
+            width,
+            height: lines.len(),
+            cells,
+        })
+    }
+
+    /// What can be seen of and from a single tree, per direction in `DIRECTIONS` order.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    struct TreeView {
+        visible_from: [bool; 4],
+        viewing_distance: [usize; 4],
+    }
+
+    impl TreeView {
+        fn is_visible(&self) -> bool {
+            self.visible_from.iter().any(|&visible| visible)
+        }
+
+        fn scenic_score(&self) -> usize {
+            self.viewing_distance.iter().product()
+        }
+    }
+
+    /// Visibility and viewing distances of every tree, in O(n) per direction.
+    ///
+    /// Walking a line away from an edge, a stack keeps the trees that are taller than everything
+    /// after them. Every tree pops the lower trees off the stack, so the top that remains is the
+    /// tree blocking its view back towards the edge. If the stack ends up empty, nothing blocks
+    /// the view and the tree is visible from that edge.
+    fn analyze(forest: &Grid<u8>) -> Grid<TreeView> {
+        let mut views = Grid::from_fn(forest.width, forest.height, |_, _| TreeView::default());
+        for (d, &edge) in DIRECTIONS.iter().enumerate() {
+            for line in forest.lines_from(edge) {
+                let mut stack: Vec<usize> = Vec::new();
+                for (position, &(x, y)) in line.iter().enumerate() {
+                    let height = forest.get(x, y);
+                    while let Some(&top) = stack.last() {
+                        let (tx, ty) = line[top];
+                        if forest.get(tx, ty) >= height {
+                            break;
+                        }
+                        stack.pop();
+                    }
+                    let view = views.get_mut(x, y);
+                    match stack.last() {
+                        Some(&blocker) => view.viewing_distance[d] = position - blocker,
+                        None => {
+                            view.visible_from[d] = true;
+                            view.viewing_distance[d] = position;
+                        }
+                    }
+                    stack.push(position);
+                }
+            }
+        }
+        views
+    }
+
+    fn visible_tree_count(views: &Grid<TreeView>) -> usize {
+        views.cells.iter().filter(|view| view.is_visible()).count()
+    }
+
+    /// The tree with the highest scenic score, and that score.
+    fn best_tree(views: &Grid<TreeView>) -> Option<((usize, usize), usize)> {
+        views
+            .coords()
+            .map(|(x, y)| ((x, y), views.get(x, y).scenic_score()))
+            .max_by_key(|&(_, score)| score)
+    }
+
+    /// The trees seen from a tree, up to and including the ones blocking its view.
+    fn sight_lines(views: &Grid<TreeView>, (x, y): (usize, usize)) -> HashSet<(usize, usize)> {
+        let distances = views.get(x, y).viewing_distance;
+        let mut seen = HashSet::new();
+        for (&direction, &distance) in DIRECTIONS.iter().zip(distances.iter()) {
+            for step in 1..=distance {
+                seen.insert(match direction {
+                    Direction::North => (x, y - step),
+                    Direction::East => (x + step, y),
+                    Direction::South => (x, y + step),
+                    Direction::West => (x - step, y),
+                });
+            }
+        }
+        seen
+    }
+
+    /// A value between 0 and 1 per tree, drawn as a color under a label, with the best tree and
+    /// its sight lines picked out.
+    struct Heatmap {
+        heat: Grid<f64>,
+        labels: Grid<char>,
+        focus: Option<(usize, usize)>,
+        highlighted: HashSet<(usize, usize)>,
+    }
+
+    /// Box drawing characters, indexed by a bit per direction in `DIRECTIONS` order.
+    const DIRECTION_GLYPHS: [char; 16] = [
+        '·', '╵', '╶', '└', '╷', '│', '┌', '├', '╴', '┘', '─', '┴', '┐', '┤', '┬', '┼',
+    ];
+
+    impl Heatmap {
+        fn new(heat: Grid<f64>, labels: Grid<char>, views: &Grid<TreeView>) -> Heatmap {
+            let focus = best_tree(views).map(|(tree, _)| tree);
+            let highlighted = focus.map_or_else(HashSet::new, |tree| sight_lines(views, tree));
+            Heatmap {
+                heat,
+                labels,
+                focus,
+                highlighted,
+            }
+        }
+
+        /// How many sides every tree can be seen from, labeled with lines towards those sides.
+        fn visibility(views: &Grid<TreeView>) -> Heatmap {
+            let heat = views.map(|view| {
+                view.visible_from.iter().filter(|&&visible| visible).count() as f64 / 4.0
+            });
+            let labels = views.map(|view| {
+                let mask = view
+                    .visible_from
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, &visible)| visible)
+                    .map(|(d, _)| 1 << d)
+                    .sum::<usize>();
+                DIRECTION_GLYPHS[mask]
+            });
+            Heatmap::new(heat, labels, views)
+        }
+
+        /// The scenic score of every tree on a logarithmic scale, labeled with the tree heights.
+        fn scenic_scores(forest: &Grid<u8>, views: &Grid<TreeView>) -> Heatmap {
+            let max = best_tree(views).map_or(0, |(_, score)| score);
+            let scale = (1.0 + max as f64).ln().max(f64::MIN_POSITIVE);
+            let heat = views.map(|view| (1.0 + view.scenic_score() as f64).ln() / scale);
+            let labels = forest.map(|&height| char::from(b'0' + height));
+            Heatmap::new(heat, labels, views)
+        }
+
+        /// A colored rendering for a terminal that supports 24-bit colors.
+        fn to_ansi(&self) -> String {
+            let mut text = String::new();
+            for y in 0..self.heat.height {
+                for x in 0..self.heat.width {
+                    let (r, g, b) = match self.focus == Some((x, y)) {
+                        true => (255, 0, 255),
+                        false => heat_color(*self.heat.get(x, y)),
+                    };
+                    let foreground = match self.highlighted.contains(&(x, y)) {
+                        true => "1;96",
+                        false => "37",
+                    };
+                    write!(
+                        text,
+                        "\x1b[{foreground};48;2;{r};{g};{b}m{}",
+                        self.labels.get(x, y)
+                    )
+                    .unwrap();
+                }
+                text += "\x1b[0m\n";
+            }
+            text
+        }
+
+        fn to_svg(&self) -> String {
+            let (width, height) = (self.heat.width, self.heat.height);
+            let mut svg = format!(
+                "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 {width} {height}\">\n"
+            );
+            for (x, y) in self.heat.coords() {
+                let (r, g, b) = heat_color(*self.heat.get(x, y));
+                svg += &format!(
+                    "  <rect x=\"{x}\" y=\"{y}\" width=\"1\" height=\"1\" fill=\"rgb({r},{g},{b})\"/>\n"
+                );
+            }
+            for &(x, y) in self.highlighted.iter().sorted() {
+                svg += &format!(
+                    "  <rect x=\"{}\" y=\"{}\" width=\"0.8\" height=\"0.8\" fill=\"none\" stroke=\"cyan\" stroke-width=\"0.2\"/>\n",
+                    x as f64 + 0.1,
+                    y as f64 + 0.1
+                );
+            }
+            if let Some((x, y)) = self.focus {
+                svg += &format!(
+                    "  <rect x=\"{x}\" y=\"{y}\" width=\"1\" height=\"1\" fill=\"magenta\"/>\n"
+                );
+            }
+            svg += "</svg>\n";
+            svg
+        }
+    }
+
+    /// A color from dark purple through red and orange to pale yellow.
+    fn heat_color(heat: f64) -> (u8, u8, u8) {
+        const STOPS: [(f64, f64, f64); 5] = [
+            (0.0, 0.0, 4.0),
+            (87.0, 16.0, 110.0),
+            (188.0, 55.0, 84.0),
+            (249.0, 142.0, 9.0),
+            (252.0, 255.0, 164.0),
+        ];
+        let position = heat.clamp(0.0, 1.0) * (STOPS.len() - 1) as f64;
+        let index = (position as usize).min(STOPS.len() - 2);
+        let t = position - index as f64;
+        let (from, to) = (STOPS[index], STOPS[index + 1]);
+        let mix = |a: f64, b: f64| (a + (b - a) * t).round() as u8;
+        (mix(from.0, to.0), mix(from.1, to.1), mix(from.2, to.2))
+    }
+
+    #[test]
+    fn day8_sample() -> Result<(), Box<dyn Error>> {
+        let forest = parse(&get_sample_input(8)?)?;
+        let views = analyze(&forest);
+
+        assert!(visible_tree_count(&views) == 21);
+        assert!(best_tree(&views) == Some(((2, 3), 8)));
+
+        // the middle 5 in the second row can only be seen from the top and from the right
+        let view = views.get(2, 1);
+        assert!(view.visible_from == [true, true, false, false]);
+        assert!(view.viewing_distance == [1, 2, 2, 1]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn day8_heatmaps() -> Result<(), Box<dyn Error>> {
+        let forest = parse(&get_sample_input(8)?)?;
+        let views = analyze(&forest);
+
+        let visibility = Heatmap::visibility(&views);
+        assert!(visibility
+            .labels
+            .cells
+            .iter()
+            .collect::<String>()
+            .starts_with("┘╵╵┴└"));
+        assert!(*visibility.labels.get(2, 1) == '└');
+        assert!(*visibility.labels.get(1, 3) == '·');
+        assert!(*visibility.heat.get(0, 0) == 0.5);
+
+        let scenic = Heatmap::scenic_scores(&forest, &views);
+        assert!(scenic.focus == Some((2, 3)));
+        assert!(*scenic.heat.get(2, 3) == 1.0 && *scenic.heat.get(0, 0) == 0.0);
+        assert!(
+            scenic.highlighted
+                == HashSet::from([(2, 2), (2, 1), (3, 3), (4, 3), (2, 4), (1, 3), (0, 3)])
+        );
+
+        let ansi = scenic.to_ansi();
+        assert!(ansi.lines().count() == 5);
+        assert!(ansi.matches("\x1b[1;96").count() == 7);
+        assert!(ansi.contains("48;2;255;0;255m5"));
+        assert!(ansi.lines().next().unwrap().ends_with("3\x1b[0m"));
+
+        let svg = scenic.to_svg();
+        assert!(svg.matches("<rect").count() == 25 + 7 + 1);
+        assert!(svg.contains("fill=\"rgb(0,0,4)\""));
+        assert!(svg.contains("fill=\"rgb(252,255,164)\""));
+
+        Ok(())
+    }
+
+    #[test]
+    fn day8_matches_brute_force() -> Result<(), Box<dyn Error>> {
+        let mut random = pseudo_random(8);
+        for (width, height) in [(5, 5), (1, 7), (7, 1), (13, 9), (40, 40)] {
+            let forest = Grid::from_fn(width, height, |_, _| (random.next().unwrap() % 10) as u8);
+            let views = analyze(&forest);
+
+            let heights = forest.cells.iter().map(|&h| h as usize).collect_vec();
+            let visible = brute_force_visible_trees(&heights, width, height);
+            for (x, y) in forest.coords() {
+                let view = views.get(x, y);
+                assert!(view.is_visible() == visible.contains(&(y, x)), "({x}, {y})");
+
+                let (north, east, south, west) =
+                    brute_force_scenic_score(x, y, &heights, width, height);
+                assert!(
+                    view.scenic_score() == north * east * south * west,
+                    "({x}, {y})"
+                );
+                assert!(
+                    view.viewing_distance == [north, east, south, west],
+                    "({x}, {y})"
+                );
+            }
+        }
+
+        Ok(())
+    }
+
     #[test]
     fn day8() -> Result<(), Box<dyn std::error::Error>> {
         let input = get_input(8)?;
+        let forest = parse(&input)?;
+        let views = analyze(&forest);
 
-        let mut width = None;
-        let mut height = 0;
-        let mut matrix: Vec<usize> = vec![];
-        for line in input.lines() {
-            height += 1;
-            if width == None {
-                width = Some(line.len());
-            }
-            for char in line.chars() {
-                matrix.push(char.to_string().parse().unwrap());
-            }
+        println!(
+            "Number of trees visible from the outside: {}",
+            visible_tree_count(&views)
+        );
+        if let Some(((x, y), score)) = best_tree(&views) {
+            let distances = views.get(x, y).viewing_distance;
+            println!("highscore for ({x}, {y}): {score} ({distances:?})");
+        }
+
+        let visibility = Heatmap::visibility(&views);
+        let scenic = Heatmap::scenic_scores(&forest, &views);
+        println!("{}", visibility.to_ansi());
+        println!("{}", scenic.to_ansi());
+        for (name, heatmap) in [("visibility", visibility), ("scenic", scenic)] {
+            let path = std::env::temp_dir().join(format!("day8_{name}.svg"));
+            std::fs::write(&path, heatmap.to_svg())?;
+            println!("wrote the {name} heatmap to {path:?}");
         }
 
-        let width = width.unwrap();
+        Ok(())
+    }
+
+    /// The original visibility check, kept as an oracle for the tests.
+    /// Returns the visible trees as (row, column).
+    fn brute_force_visible_trees(
+        matrix: &[usize],
+        width: usize,
+        height: usize,
+    ) -> HashSet<(usize, usize)> {
         fn collect_visible_trees(matrix: &[usize], width: usize) -> HashSet<(usize, usize)> {
             let mut visible_trees: HashSet<(usize, usize)> = HashSet::new();
             let mut max_tree_height = None;
@@ -69,91 +471,74 @@ mod tests {
             visible_trees
         }
 
-        let mut visible_trees = collect_visible_trees(&matrix, width);
+        let mut visible_trees = collect_visible_trees(matrix, width);
         let mut transposed = vec![0; matrix.len()];
 
-        transpose(&matrix, &mut transposed, width, height);
+        transpose(matrix, &mut transposed, width, height);
         let transposed_visible_trees = collect_visible_trees(&transposed, height);
         visible_trees.extend(transposed_visible_trees.into_iter().map(|(x, y)| (y, x)));
+        visible_trees
+    }
 
-        println!(
-            "Number of trees visible from the outside: {}",
-            visible_trees.len()
-        );
+    /// The original viewing distances (north, east, south, west), kept as an oracle for the tests.
+    fn brute_force_scenic_score(
+        x: usize,
+        y: usize,
+        forest: &[usize],
+        forest_width: usize,
+        forest_height: usize,
+    ) -> (usize, usize, usize, usize) {
+        let tree_index = (y * forest_width) + x;
+        let tree_height = forest[tree_index];
 
-        fn scenic_score(
-            x: usize,
-            y: usize,
-            forest: &[usize],
-            forest_width: usize,
-            forest_height: usize,
-        ) -> (usize, usize, usize, usize) {
-            let tree_index = (y * forest_width) + x;
-            let tree_height = forest[tree_index];
-
-            let mut score_west = 0;
-            {
-                let mut i = tree_index;
-                while i > y * forest_width {
-                    score_west += 1;
-                    i -= 1; //cannot underflow because of condition above;
-                    if forest[i] >= tree_height {
-                        break;
-                    }
+        let mut score_west = 0;
+        {
+            let mut i = tree_index;
+            while i > y * forest_width {
+                score_west += 1;
+                i -= 1; //cannot underflow because of condition above;
+                if forest[i] >= tree_height {
+                    break;
                 }
             }
+        }
 
-            let mut score_north = 0;
-            {
-                let mut i = tree_index;
-                while i > forest_width {
-                    score_north += 1;
-                    i -= forest_width; //up a row (cannot underflow because of while condition)
-                    if forest[i] >= tree_height {
-                        break;
-                    }
+        let mut score_north = 0;
+        {
+            let mut i = tree_index;
+            while i >= forest_width {
+                score_north += 1;
+                i -= forest_width; //up a row (cannot underflow because of while condition)
+                if forest[i] >= tree_height {
+                    break;
                 }
             }
-            let mut score_east = 0;
-            {
-                let mut i = tree_index;
-                let end_of_row = ((y + 1) * forest_width) - 1;
-                while i < end_of_row {
-                    score_east += 1;
-                    i += 1;
-                    if forest[i] >= tree_height {
-                        break;
-                    }
-                }
-            }
-            let mut score_south = 0;
-            {
-                let mut i = tree_index;
-                let end_of_col = (forest_width * (forest_height - 1)) + x;
-                while i < end_of_col {
-                    score_south += 1;
-                    i += forest_width;
-                    if forest[i] >= tree_height {
-                        break;
-                    }
+        }
+        let mut score_east = 0;
+        {
+            let mut i = tree_index;
+            let end_of_row = ((y + 1) * forest_width) - 1;
+            while i < end_of_row {
+                score_east += 1;
+                i += 1;
+                if forest[i] >= tree_height {
+                    break;
                 }
             }
-
-            (score_north, score_east, score_south, score_west)
         }
-
-        let mut max_score = 0;
-        for y in 0..height {
-            for x in 0..width {
-                let scores = scenic_score(x, y, &matrix, width, height);
-                let score = scores.0 * scores.1 * scores.2 * scores.3;
-                if score >= max_score {
-                    println!("new highscore for ({x}, {y}): {score} ({scores:?})");
-                    max_score = score;
+        let mut score_south = 0;
+        {
+            let mut i = tree_index;
+            let end_of_col = (forest_width * (forest_height - 1)) + x;
+            while i < end_of_col {
+                score_south += 1;
+                i += forest_width;
+                if forest[i] >= tree_height {
+                    break;
                 }
             }
         }
 
-        Ok(())
+        (score_north, score_east, score_south, score_west)
     }
 }
@@ -3,8 +3,11 @@ mod tests {
 
     use crate::common::tests::get_input;
     use std::collections::HashSet;
+    use std::error::Error;
     use std::{thread, time};
 
+    type Coord = (i32, i32);
+
     #[test]
     fn day9_part1() -> Result<(), Box<dyn std::error::Error>> {
         day9_impl(2, false)
@@ -20,87 +23,209 @@ mod tests {
         day9_impl(10, false)
     }
 
-    fn day9_impl(rope_size: usize, debug: bool) -> Result<(), Box<dyn std::error::Error>> {
-        let mut rope: Vec<(i32, i32)> = vec![(0, 0); rope_size];
+    /// A number of steps of the head in one of eight directions.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    struct Motion {
+        direction: Coord,
+        steps: usize,
+    }
+
+    /// Reads motions like `R 4`, or `UL 2` for diagonal moves.
+    fn parse_motions(input: &str) -> Result<Vec<Motion>, Box<dyn Error>> {
+        let mut motions = Vec::new();
+        for line in input.lines().filter(|line| !line.trim().is_empty()) {
+            let (direction, steps) = line
+                .split_once(' ')
+                .ok_or_else(|| format!("Not a motion: {line}"))?;
+            let mut offset: Coord = (0, 0);
+            for c in direction.chars() {
+                let (dx, dy) = match c {
+                    'D' => (0, -1),
+                    'U' => (0, 1),
+                    'R' => (1, 0),
+                    'L' => (-1, 0),
+                    _ => Err(format!("Unsupported dir char {c}"))?,
+                };
+                offset = (offset.0 + dx, offset.1 + dy);
+            }
+            if offset == (0, 0) || offset.0.abs() > 1 || offset.1.abs() > 1 {
+                Err(format!("Unsupported direction {direction}"))?
+            }
+            motions.push(Motion {
+                direction: offset,
+                steps: steps.trim().parse()?,
+            });
+        }
+        Ok(motions)
+    }
+
+    /// A rope of knots, each following the one before it, remembering where every knot has been.
+    struct Rope {
+        knots: Vec<Coord>,
+        visited: Vec<HashSet<Coord>>,
+    }
+
+    impl Rope {
+        fn new(knot_count: usize) -> Rope {
+            assert!(knot_count > 0, "A rope needs a head");
+            Rope {
+                knots: vec![(0, 0); knot_count],
+                visited: vec![HashSet::from([(0, 0)]); knot_count],
+            }
+        }
 
-        fn touching(head: &(i32, i32), tail: &(i32, i32)) -> bool {
+        fn touching(head: &Coord, tail: &Coord) -> bool {
             (head.0 - tail.0).abs() <= 1 && (head.1 - tail.1).abs() <= 1
         }
 
-        fn diag(target: &(i32, i32), current: &(i32, i32)) -> (i32, i32) {
-            (
-                (target.0 - current.0).signum(),
-                (target.1 - current.1).signum(),
-            )
+        /// Moves the head by a single step, in any of the eight directions, and lets the rest of
+        /// the rope follow.
+        fn step(&mut self, (dx, dy): Coord) {
+            assert!(dx.abs() <= 1 && dy.abs() <= 1, "Not a single step");
+            self.knots[0] = (self.knots[0].0 + dx, self.knots[0].1 + dy);
+            self.visited[0].insert(self.knots[0]);
+
+            for i in 1..self.knots.len() {
+                let (front, back) = (self.knots[i - 1], self.knots[i]);
+                if Rope::touching(&front, &back) {
+                    // the knots behind this one won't move either
+                    break;
+                }
+                self.knots[i] = (
+                    back.0 + (front.0 - back.0).signum(),
+                    back.1 + (front.1 - back.1).signum(),
+                );
+                self.visited[i].insert(self.knots[i]);
+            }
         }
 
-        fn print_rope(rope: &[(i32, i32)]) {
-            let mut set = HashSet::new();
-            for c in rope {
-                set.insert(c.clone());
+        fn apply(&mut self, motion: &Motion) {
+            for _ in 0..motion.steps {
+                self.step(motion.direction);
             }
-            for y in (-25..25).rev() {
-                for x in -15..15 {
-                    if rope[0] == (x, y) {
-                        print!("H")
-                    } else if set.contains(&(x, y)) {
-                        print!("X")
-                    } else {
-                        print!(".");
-                    }
-                }
-                println!();
+        }
+
+        fn tail(&self) -> usize {
+            self.knots.len() - 1
+        }
+
+        /// Every position the given knot has been in, the head being knot 0.
+        fn visited(&self, knot: usize) -> &HashSet<Coord> {
+            &self.visited[knot]
+        }
+
+        fn label(&self, knot: usize) -> char {
+            match knot {
+                0 => 'H',
+                _ if self.knots.len() == 2 => 'T',
+                1..=9 => char::from_digit(knot as u32, 10).unwrap(),
+                _ => '#',
             }
         }
 
-        let mut tail_visited = HashSet::new();
-        tail_visited.insert(rope.last().unwrap().clone());
+        /// The rope as the puzzle draws it, in just enough space to show it and the start.
+        /// Knots closer to the head are drawn over the ones behind them.
+        fn render(&self) -> String {
+            render(self.knots.iter().copied().chain([(0, 0)]), |position| {
+                self.knots
+                    .iter()
+                    .position(|&knot| knot == position)
+                    .map(|knot| self.label(knot))
+                    .or((position == (0, 0)).then_some('s'))
+            })
+        }
+
+        /// The positions visited by the given knot, as the puzzle draws them.
+        fn render_visited(&self, knot: usize) -> String {
+            let visited = self.visited(knot);
+            render(visited.iter().copied(), |position| match position {
+                (0, 0) => Some('s'),
+                _ => visited.contains(&position).then_some('#'),
+            })
+        }
+    }
+
+    /// Draws the bounding box of the given positions, north at the top.
+    fn render(
+        positions: impl Iterator<Item = Coord>,
+        cell: impl Fn(Coord) -> Option<char>,
+    ) -> String {
+        let (mut min, mut max) = ((i32::MAX, i32::MAX), (i32::MIN, i32::MIN));
+        for (x, y) in positions {
+            min = (min.0.min(x), min.1.min(y));
+            max = (max.0.max(x), max.1.max(y));
+        }
+        let mut text = String::new();
+        for y in (min.1..=max.1).rev() {
+            for x in min.0..=max.0 {
+                text.push(cell((x, y)).unwrap_or('.'));
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    #[test]
+    fn day9_sample() -> Result<(), Box<dyn std::error::Error>> {
+        let motions = parse_motions("R 4\nU 4\nL 3\nD 1\nR 4\nD 1\nL 5\nR 2")?;
+
+        let mut rope = Rope::new(2);
+        motions.iter().for_each(|motion| rope.apply(motion));
+        assert!(rope.visited(rope.tail()).len() == 13);
+        assert!(rope.render() == ".TH\n...\ns..\n");
+        assert!(rope.render_visited(1) == "..##.\n...##\n.####\n....#\ns###.\n");
+
+        let mut rope = Rope::new(10);
+        motions.iter().for_each(|motion| rope.apply(motion));
+        assert!(rope.visited(rope.tail()).len() == 1);
+        assert!(rope.visited(1).len() == 13);
+        assert!(rope.render() == ".1H3\n.5..\n6...\n");
+
+        let motions = parse_motions("R 5\nU 8\nL 8\nD 3\nR 17\nD 10\nL 25\nU 20")?;
+        let mut rope = Rope::new(10);
+        motions.iter().for_each(|motion| rope.apply(motion));
+        assert!(rope.visited(rope.tail()).len() == 36);
+
+        Ok(())
+    }
+
+    #[test]
+    fn day9_diagonal_moves() -> Result<(), Box<dyn std::error::Error>> {
+        let mut rope = Rope::new(3);
+        for motion in parse_motions("UR 3\nL 1\nDL 2")? {
+            rope.apply(&motion);
+        }
+        assert!(rope.knots == vec![(0, 1), (1, 1), (1, 1)]);
+        assert!(rope.visited(1) == &HashSet::from([(0, 0), (1, 1), (2, 2)]));
+        assert!(rope.visited(2) == &HashSet::from([(0, 0), (1, 1)]));
+        assert!(rope.render() == "H1\ns.\n");
+
+        assert!(parse_motions("UD 1").is_err());
+        assert!(parse_motions("X 1").is_err());
+        assert!(parse_motions("UU 1").is_err());
+
+        Ok(())
+    }
+
+    fn day9_impl(rope_size: usize, debug: bool) -> Result<(), Box<dyn std::error::Error>> {
+        let mut rope = Rope::new(rope_size);
 
         let input = get_input(9)?;
-        // let input = "R 4\nU 4\nL 3\nD 1\nR 4\nD 1\nL 5\nR 2\n";
-        for line in input.lines() {
-            let direction = line.chars().nth(0).unwrap();
-            let count: usize = (&line[2..]).parse()?;
-
-            let dir = match direction {
-                'D' => (0, -1),
-                'U' => (0, 1),
-                'R' => (1, 0),
-                'L' => (-1, 0),
-                _ => panic!("Unsupported dir char {direction}"),
-            };
-
-            for _ in 0..count {
-                let head = rope.first_mut().unwrap();
-                head.0 += dir.0;
-                head.1 += dir.1;
-
-                for i in 0..rope.len() - 1 {
-                    let (car, cdr) = rope.split_at_mut(i + 1);
-                    let front = car.last().unwrap();
-                    let back = cdr.first_mut().unwrap();
-
-                    if !touching(front, back) {
-                        let d = diag(&front, &back);
-                        back.0 += d.0;
-                        back.1 += d.1;
-                    }
-                }
+        for motion in parse_motions(&input)? {
+            for _ in 0..motion.steps {
+                rope.step(motion.direction);
 
                 if debug {
-                    print_rope(&rope);
-                    println!();
+                    println!("{}", rope.render());
                     let ten_millis = time::Duration::from_millis(300);
                     thread::sleep(ten_millis);
                 }
-
-                tail_visited.insert(rope.last().unwrap().clone());
             }
         }
 
         println!(
             "Number of places visited by the tail: {}",
-            tail_visited.len()
+            rope.visited(rope.tail()).len()
         );
 
         Ok(())
@@ -25,6 +25,20 @@ mod day23;
 mod day24;
 mod day25;
 
-fn main() {
-    println!("Run `cargo test` to run the code for the separate days");
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        ["day13-compare", left, right] => day13::compare_command(left, right)?,
+        _ => {
+            println!("Run `cargo test` to run the code for the separate days");
+            println!("Other commands:");
+            println!("  day13-compare <left packet> <right packet>");
+        }
+    }
+    Ok(())
 }